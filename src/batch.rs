@@ -0,0 +1,44 @@
+use timely::dataflow::scopes::root::Root;
+use timely_communication::Allocate;
+
+use graphmap::{Graph, Node};
+use teleport::Teleport;
+use {Config, compute};
+
+/// Runs one personalized PageRank for each of `sources` in a single pass.
+///
+/// Every message carries one rank for each source, so that each iteration reads
+/// the transposed edges once rather than once per source. This is the dataflow
+/// of `run`, and all settings of `config` apply, except that `sources` take the
+/// place of `config.teleport`; with a tolerance, iteration stops once the L1
+/// change of every source's ranks has fallen below it.
+///
+/// Returns this worker's shard of the final ranks, as `(node, ranks)` pairs for
+/// the nodes `config.partition` assigns it, in increasing order of node, where
+/// `ranks[k]` is the rank of `node` personalized to `sources[k]`. There must be
+/// at least one source.
+pub fn run_batch<G:Graph,A:Allocate>(root:&mut Root<A>, graph:&G, config:&Config, sources:&[Teleport]) -> Vec<(Node, Vec<f32>)> {
+    assert!(!sources.is_empty(), "run_batch needs at least one source");
+    compute(root, graph, config, sources)
+}
+
+#[test]
+fn batch_matches_run() {
+    use {test_ranks, assert_ranks, Job};
+
+    let mut config = Config::new();
+    config.iterations = 10;
+    let sources = vec![Teleport::Uniform, Teleport::seeds(&[1, 5]), Teleport::Weights(vec![(0, 1.0), (9, 3.0)])];
+    let mut jobs = vec![Job::Batch(config.clone(), sources.clone())];
+    for source in sources {
+        let mut single = config.clone();
+        single.teleport = source;
+        jobs.push(Job::Run(single));
+    }
+    for &workers in &[1, 3] {
+        let ranks = test_ranks(workers, jobs.clone());
+        for k in 0..3 {
+            assert_ranks(&ranks[k], &ranks[k + 3]);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+extern crate pagerank;
+
+use std::io::{ BufWriter, Write };
+
+use pagerank::graphmap::{ GraphMMap, Graph, WeightedGraph, edge_list };
+
+// writes the edges of an encoded graph to stdout as `src\tdst` lines, with a
+// third `weight` column for weighted graphs, in the format `parse` reads.
+//
+// `--nodes first..limit` restricts the output to edges from nodes in the range;
+// either end may be omitted.
+
+fn main() {
+    eprintln!("usage: dump <source> [--nodes first..limit]");
+    let source = std::env::args().skip(1).next().unwrap();
+    let args: Vec<String> = std::env::args().skip(2).collect();
+
+    let graph = match GraphMMap::new(&source) {
+        Ok(graph) => graph,
+        Err(error) => { eprintln!("error loading graph: {}", error); std::process::exit(1); }
+    };
+
+    let mut nodes = 0 .. graph.nodes();
+    if let Some(position) = args.iter().position(|x| x == "--nodes") {
+        let range = args.get(position + 1).expect("--nodes requires a range");
+        let mut ends = range.splitn(2, "..");
+        let first = ends.next().unwrap();
+        let limit = ends.next().expect("node range must be first..limit");
+        if first.len() > 0 { nodes.start = first.parse().ok().expect("malformed first node"); }
+        if limit.len() > 0 { nodes.end = limit.parse().ok().expect("malformed limit node"); }
+    }
+
+    let output = std::io::stdout();
+    let mut writer = BufWriter::new(output.lock());
+
+    // stop quietly when the reader goes away, e.g. when piped into `head`
+    let result = if graph.weighted() {
+        let limit = std::cmp::min(nodes.end, graph.nodes());
+        (nodes.start .. limit).flat_map(|node| {
+            graph.edges(node).iter().zip(graph.weights(node)).map(move |(&target, &weight)| (node, target, weight))
+        })
+        .map(|(source, target, weight)| writeln!(writer, "{}\t{}\t{}", source, target, weight))
+        .collect::<Result<(), _>>()
+    }
+    else {
+        edge_list(&graph, nodes)
+            .map(|(source, target)| writeln!(writer, "{}\t{}", source, target))
+            .collect::<Result<(), _>>()
+    };
+
+    if let Err(error) = result.and_then(|_| writer.flush()) {
+        if error.kind() != std::io::ErrorKind::BrokenPipe {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
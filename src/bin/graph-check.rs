@@ -0,0 +1,29 @@
+extern crate pagerank;
+
+use pagerank::check;
+
+fn main() {
+    println!("usage: graph-check <source> [--thorough]");
+    let source = std::env::args().skip(1).next().unwrap();
+    let thorough = std::env::args().skip(2).any(|x| x == "--thorough");
+
+    let report = match check::check(&source, thorough) {
+        Ok(report) => report,
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+    };
+
+    println!("nodes: {}", report.nodes);
+    println!("edges: {}", report.edges);
+    if thorough {
+        println!("self-loops: {}", report.self_loops);
+        println!("duplicate edges: {}", report.duplicates);
+        println!("unsorted adjacency lists: {}", report.unsorted);
+    }
+
+    for error in &report.errors {
+        eprintln!("error: {}", error);
+    }
+
+    if report.valid() { println!("ok"); }
+    else              { std::process::exit(1); }
+}
@@ -2,7 +2,12 @@ extern crate timely;
 extern crate getopts;
 extern crate pagerank;
 
-use pagerank::graphmap::GraphMMap;
+use pagerank::{Config, Dangling};
+use pagerank::graphmap::{self, Graph, GraphMMap, Node};
+use pagerank::cache::{self, Cache};
+use pagerank::teleport::Teleport;
+use pagerank::partition::{Partition, Partitioning};
+use pagerank::output::{self, Format, RankFile};
 
 
 fn main () {
@@ -16,19 +21,209 @@ fn main () {
     opts.optopt("p", "process", "", "");
     opts.optopt("n", "processes", "", "");
     opts.optopt("h", "hostfile", "", "");
+    opts.optopt("", "iterations", "maximum number of iterations (default: 20)", "N");
+    opts.optopt("", "tolerance", "stop once the L1 change in ranks falls below EPS", "EPS");
+    opts.optopt("", "damping", "probability of following an edge (default: 0.85)", "ALPHA");
+    opts.optopt("", "dangling", "where rank of nodes without out-edges goes (default: uniform)", "uniform|teleport");
+    opts.optopt("", "seeds", "teleport only to these nodes", "N,N,...");
+    opts.optopt("", "teleport", "teleport by the (node, weight) lines of FILE", "FILE");
+    opts.optflag("", "weighted", "split rank by the edge weights in <source>.weights");
+    opts.optopt("", "partition", "how nodes are spread over workers (default: modulo)", "modulo|range|balanced");
+    opts.optopt("", "hubs", "combine partial sums for nodes with more than D in-edges in each process first", "D");
+    opts.optflag("", "grid", "partition edges over a grid of workers, rather than by source");
+    opts.optflag("", "sharded", "have each worker map only its shard, as written by the shard binary");
+    opts.optopt("", "cache", "keep each worker's transposed edges in PREFIX-<index>-of-<workers> files", "PREFIX");
+    opts.optopt("", "initial", "start from the ranks in FILE, as written with --merge", "FILE");
+    opts.optopt("", "output", "write each worker's ranks to PREFIX-<index>", "PREFIX");
+    opts.optopt("", "format", "format of written ranks (default: binary)", "binary|text");
+    opts.optflag("", "merge", "merge the written ranks into one file, PREFIX.ranks or PREFIX.tsv");
+    opts.optopt("", "top", "print the K highest ranked nodes", "K");
+    opts.optflag("", "labels", "name nodes in text output by their labels in <source>.labels");
 
     if let Ok(matches) = opts.parse(std::env::args().skip(3)) {
 
-        let graph = GraphMMap::new(&filename);
-        let workers: usize = matches.opt_str("w").map(|x| x.parse().unwrap_or(1)).unwrap_or(1);
-        timely::execute_from_args(std::env::args().skip(2), move |root| {
-            ::pagerank::run(workers, root, &graph, strategy, 20)
+        // with --sharded, each process maps only the shards of its own workers
+        let sharded = matches.opt_present("sharded");
+        let graph = if sharded { None } else {
+            match GraphMMap::new(&filename) {
+                Ok(graph) => Some(graph),
+                Err(error) => { eprintln!("error loading graph: {}", error); std::process::exit(1); }
+            }
+        };
+
+        let mut config = Config::new();
+        config.workers = matches.opt_str("w").map(|x| x.parse().unwrap_or(1)).unwrap_or(1);
+        config.use_process_local = strategy;
+        if let Some(iterations) = matches.opt_str("iterations") {
+            config.iterations = iterations.parse().ok().expect("malformed iterations");
+        }
+        if let Some(tolerance) = matches.opt_str("tolerance") {
+            config.tolerance = Some(tolerance.parse().ok().expect("malformed tolerance"));
+        }
+        if let Some(damping) = matches.opt_str("damping") {
+            config.damping = damping.parse().ok().expect("malformed damping");
+            assert!(config.damping >= 0.0 && config.damping < 1.0, "damping must lie in [0, 1)");
+        }
+        if let Some(dangling) = matches.opt_str("dangling") {
+            config.dangling = match &dangling[..] {
+                "uniform"  => Dangling::Uniform,
+                "teleport" => Dangling::Teleport,
+                _          => panic!("unknown dangling strategy: {}", dangling),
+            };
+        }
+        let seeds = matches.opt_str("seeds").map(|seeds| seeds.split(',').map(|x| x.parse().ok().expect("malformed seed")).collect::<Vec<Node>>());
+        if let Some(ref seeds) = seeds {
+            config.teleport = Teleport::seeds(seeds);
+        }
+        if let Some(teleport) = matches.opt_str("teleport") {
+            config.teleport = match Teleport::load(&teleport) {
+                Ok(teleport) => teleport,
+                Err(error) => { eprintln!("error loading teleport weights: {}", error); std::process::exit(1); }
+            };
+        }
+        if let Some(initial) = matches.opt_str("initial") {
+            config.initial = match RankFile::open(&initial) {
+                Ok(initial) => Some(initial),
+                Err(error) => { eprintln!("error loading initial ranks: {}", error); std::process::exit(1); }
+            };
+        }
+        if let Some(partition) = matches.opt_str("partition") {
+            config.partition = Partitioning::from_name(&partition).unwrap_or_else(|| panic!("unknown partition: {}", partition));
+            assert!(!sharded || config.partition == Partitioning::Modulo, "shards are partitioned by modulo");
+        }
+
+        // timely only understands its own options
+        let mut timely_args = vec![];
+        for &opt in &["w", "p", "n", "h"] {
+            if let Some(value) = matches.opt_str(opt) {
+                timely_args.push(format!("-{}", opt));
+                timely_args.push(value);
+            }
+        }
+
+        // timely numbers the workers of process `p` from `p * workers`
+        let processes = matches.opt_str("n").map(|x| x.parse().unwrap_or(1)).unwrap_or(1);
+        let process = matches.opt_str("p").map(|x| x.parse().unwrap_or(0)).unwrap_or(0);
+        let first = process * config.workers;
+        let mut shards = vec![];
+        if sharded {
+            for index in first .. first + config.workers {
+                match GraphMMap::new_shard(&filename, index, config.workers * processes) {
+                    Ok(shard) => shards.push(shard),
+                    Err(error) => { eprintln!("error loading graph shard: {}", error); std::process::exit(1); }
+                }
+            }
+        }
+
+        // each shard reports the nodes of the whole graph
+        let nodes = graph.as_ref().or(shards.first()).unwrap().nodes();
+        if let Some(&seed) = seeds.iter().flat_map(|seeds| seeds.iter()).find(|&&seed| seed as usize >= nodes) {
+            eprintln!("error: seed {} is not a node of {}, which has {} nodes", seed, filename, nodes);
+            std::process::exit(1);
+        }
+        if let Some(teleport) = matches.opt_str("teleport") {
+            if config.teleport.total(nodes) <= 0.0 {
+                eprintln!("error loading teleport weights: {}: no node of {} has a positive weight", teleport, filename);
+                std::process::exit(1);
+            }
+        }
+
+        let weighted = matches.opt_present("weighted");
+        if weighted && graph.iter().chain(shards.iter()).any(|graph| !graph.weighted()) {
+            eprintln!("error loading graph: --weighted requires {}.weights", filename);
+            std::process::exit(1);
+        }
+        let cache = matches.opt_str("cache");
+        let hubs = matches.opt_str("hubs").map(|x| x.parse::<usize>().ok().expect("malformed hub degree"));
+        assert!(hubs.is_none() || !sharded, "--hubs needs the whole graph on every worker");
+        let grid = matches.opt_present("grid");
+        assert!(hubs.is_none() || !(grid || weighted), "--hubs does not support --grid or --weighted");
+        assert!(!(grid && weighted), "--grid does not support --weighted");
+        assert!(!(grid && cache.is_some()), "--grid does not support --cache");
+        assert!(!(grid && strategy), "--grid does not support process aggregation");
+        assert!(!(cache.is_some() && weighted), "--cache does not support --weighted");
+        assert!(!grid || config.partition == Partitioning::Modulo, "--grid does not support --partition");
+        if let Some(degree) = hubs {
+            // every worker of the process shares one count of in-edges
+            config.hubs = ::pagerank::hubs(graph.as_ref().unwrap(), degree);
+        }
+
+        let prefix = matches.opt_str("output");
+        let merge = matches.opt_present("merge");
+        let top = matches.opt_str("top").map(|x| x.parse::<usize>().ok().expect("malformed top"));
+        let labels = if matches.opt_present("labels") {
+            match ::pagerank::labels::read(&filename) {
+                Ok(labels) => Some(labels),
+                Err(error) => { eprintln!("error loading labels: {}", error); std::process::exit(1); }
+            }
+        }
+        else { None };
+        if let Some(ref labels) = labels {
+            if labels.len() < nodes {
+                eprintln!("error loading labels: {}.labels has {} labels for {} nodes", filename, labels.len(), nodes);
+                std::process::exit(1);
+            }
+        }
+        let format = match matches.opt_str("format").as_ref().map(|x| &x[..]) {
+            None | Some("binary") => Format::Binary,
+            Some("text")          => Format::Text,
+            Some(format)          => panic!("unknown output format: {}", format),
+        };
+
+        timely::execute_from_args(timely_args.into_iter(), move |root| {
+
+            let graph = graph.as_ref().unwrap_or_else(|| &shards[root.index() - first]);
+
+            // the cache is keyed by the files this worker actually maps
+            let mut config = config.clone();
+            if let Some(ref prefix) = cache {
+                let source = if sharded { graphmap::shard_name(&filename, root.index(), root.peers()) }
+                             else       { filename.clone() };
+                let key = match cache::key(&source) {
+                    Ok(key) => key,
+                    Err(error) => { eprintln!("error reading graph for cache key: {}", error); std::process::exit(1); }
+                };
+                config.cache = Some(Cache { prefix: prefix.clone(), key: key });
+            }
+
+            let shard = if weighted  { ::pagerank::weighted::run_weighted(root, graph, &config) }
+                        else if grid { ::pagerank::grid::run_2d(root, graph, &config) }
+                        else         { ::pagerank::run(root, graph, &config) };
+
+            if let Some(ref prefix) = prefix {
+                let index = root.index();
+                let written = output::write(&output::shard_name(prefix, index, format), &shard, format, labels.as_ref().map(|x| &x[..]));
+                if let Err(error) = written {
+                    eprintln!("error writing ranks: {}", error);
+                    std::process::exit(1);
+                }
+                if merge {
+                    // wait until every worker has written its shard
+                    ::pagerank::gather(root, Vec::new());
+                    if index == 0 {
+                        if let Err(error) = output::merge(prefix, &Partition::new(config.partition, graph, root.peers()), format) {
+                            eprintln!("error merging ranks: {}", error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+
+            if let Some(k) = top {
+                for (node, rank) in ::pagerank::topk::top_k(root, &shard, k) {
+                    match labels {
+                        Some(ref labels) => println!("{}\t{}", labels[node as usize], rank),
+                        None             => println!("{}\t{}", node, rank),
+                    }
+                }
+            }
         })
 
     }
     else {
-        println!("error parsing arguments");
-        println!("usage:\tpagerank <source> (worker|process) [timely options]");
+        eprintln!("error parsing arguments");
+        eprintln!("usage:\tpagerank <source> (worker|process) [options]");
+        eprintln!("{}", opts.usage("options:"));
+        std::process::exit(1);
     }
 }
-
@@ -1,21 +1,52 @@
-use std::io::{BufRead, BufWriter, Write};
+extern crate getopts;
+extern crate pagerank;
+
+use std::io::{BufWriter, Write};
 use std::fs::File;
 use std::mem;
 
+use pagerank::text::{ Options, Parser };
+use pagerank::input;
+use pagerank::graphmap::Node;
+
 fn main() {
-    println!("usage: parse-pairs <target>");
+    println!("usage: parse-pairs <target> [<edge-list>] [options]");
     println!("will overwrite <target>.pairs");
     let target = std::env::args().skip(1).next().unwrap();
     println!("target: {}", target);
 
+    let mut opts = getopts::Options::new();
+    Options::register(&mut opts);
+    let matches = match opts.parse(std::env::args().skip(2)) {
+        Ok(matches) => matches,
+        Err(error) => { eprintln!("error: {}", error); eprintln!("{}", opts.usage("")); std::process::exit(1); }
+    };
+    let options = match Options::from_matches(&matches, false) {
+        Ok(options) => options,
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+    };
+    let labels = options.labels;
+
     let mut pairs_writer = BufWriter::new(File::create(format!("{}.pairs", target)).unwrap());
 
-    let input = std::io::stdin();
-    for line in input.lock().lines().map(|x| x.unwrap()).filter(|x| !x.starts_with('#')) {
-        let elts: Vec<&str> = line[..].split("\t").collect();
-        let source: u32 = elts[0].parse().ok().expect("malformed source");
-        let target: u32 = elts[1].parse().ok().expect("malformed target");
-        pairs_writer.write(&unsafe { mem::transmute::<_, [u8; 4]>(source) }).unwrap();
-        pairs_writer.write(&unsafe { mem::transmute::<_, [u8; 4]>(target) }).unwrap();
+    // reads standard input if there is no edge list file
+    let filename = matches.free.get(0).map(|x| &x[..]);
+    let reader = match input::open_or_stdin(filename) {
+        Ok(reader) => reader,
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+    };
+    let mut parser = Parser::new(reader, filename.unwrap_or("<stdin>"), options);
+    for (source, target, _) in parser.by_ref() {
+        pairs_writer.write_all(&unsafe { mem::transmute::<_, [u8; mem::size_of::<Node>()]>(source) }).unwrap();
+        pairs_writer.write_all(&unsafe { mem::transmute::<_, [u8; mem::size_of::<Node>()]>(target) }).unwrap();
+    }
+    pairs_writer.flush().unwrap();
+    if labels {
+        parser.dictionary().write(&target).unwrap_or_else(|error| panic!("error writing labels: {}", error));
+    }
+
+    match parser.finish() {
+        Ok(skipped) => if skipped > 0 { println!("skipped {} malformed lines", skipped); },
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); },
     }
 }
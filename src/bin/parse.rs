@@ -1,31 +1,111 @@
+extern crate getopts;
 extern crate pagerank;
 
-use std::io::BufRead;
-
 use pagerank::encode;
+use pagerank::text::{ self, Options, Parser };
+use pagerank::input::{ self, Compression };
+use pagerank::graphmap::Node;
+use pagerank::error::Result;
 
 // output file format is
 //
 //     offset: [u64; max_src_node_id+1],
-//     target: [u32; edges],
+//     target: [u32; edges],     ([u64; edges] with the ids64 feature)
+//     weight: [f32; edges],     (only with --weighted)
 //
 // target[offset[i]..offset[i+1]] are node i's edge targets.
+//
+// input must be sorted by source unless `--unsorted` is passed, in which case it
+// is sorted in runs of RUN edges spilled to <target>.run-<i> files.
+//
+// with `--labels`, nodes are arbitrary strings, numbered in order of appearance;
+// <target>.labels then holds the label of each node, one per line.
+
+const RUN: usize = 1 << 25;
 
 fn main() {
-    println!("usage: parse <target>");
-    println!("will overwrite <target>.offsets and <target>.targets");
+    println!("usage: parse <target> [<edge-list>] [options]");
+    println!("will overwrite <target>.offsets and <target>.targets (and <target>.weights)");
     let target = std::env::args().skip(1).next().unwrap();
     println!("target: {}", target);
 
-    let input = ::std::io::stdin();
-    let mut source = input.lock().lines().map(|x| x.unwrap()).filter(|x| !x.starts_with('#')).map(|line| {
+    let mut opts = getopts::Options::new();
+    opts.optflag("", "weighted", "read an edge weight from the weight column");
+    opts.optflag("", "unsorted", "accept edges in any order, rather than sorted by source");
+    opts.optopt("", "threads", "parse an uncompressed edge list file on N threads (default: 1)", "N");
+    Options::register(&mut opts);
+
+    let matches = match opts.parse(std::env::args().skip(2)) {
+        Ok(matches) => matches,
+        Err(error) => { eprintln!("error: {}", error); eprintln!("{}", opts.usage("")); std::process::exit(1); }
+    };
+    let weighted = matches.opt_present("weighted");
+    let options = match Options::from_matches(&matches, weighted) {
+        Ok(options) => options,
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+    };
+    // labelled nodes are numbered in order of appearance, not of source
+    let unsorted = matches.opt_present("unsorted") || options.labels;
+    let labels = options.labels;
 
-        let elts: Vec<&str> = line[..].split("\t").collect();
-        let source: u32 = elts[0].parse().ok().expect("malformed source");
-        let target: u32 = elts[1].parse().ok().expect("malformed target");
+    let threads = matches.opt_str("threads").map(|x| x.parse().ok().expect("malformed threads")).unwrap_or(1);
 
-        (source,target)
-    });
+    // reads standard input if there is no edge list file
+    let filename = matches.free.get(0).map(|x| &x[..]);
+
+    // only plain files can be split, and labels must be numbered in order
+    let parallel = match filename {
+        Some(filename) if threads > 1 && !labels => match input::compression(filename) {
+            Ok(compression) => compression == Compression::Plain,
+            Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+        },
+        _ => false,
+    };
+
+    if parallel {
+        let mut chunks = match text::parse_parallel(filename.unwrap(), &target, &options, threads) {
+            Ok(chunks) => chunks,
+            Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+        };
+        let result = encode_edges(&target, &mut chunks, weighted, unsorted);
+        report(&target, result, chunks.finish());
+    }
+    else {
+        let reader = match input::open_or_stdin(filename) {
+            Ok(reader) => reader,
+            Err(error) => { eprintln!("error: {}", error); std::process::exit(1); }
+        };
+        let mut parser = Parser::new(reader, filename.unwrap_or("<stdin>"), options);
+        let result = encode_edges(&target, &mut parser, weighted, unsorted)
+                         .and_then(|_| if labels { parser.dictionary().write(&target) } else { Ok(()) });
+        report(&target, result, parser.finish());
+    }
+}
+
+fn encode_edges<I: Iterator<Item=(Node, Node, f32)>>(target: &str, edges: &mut I, weighted: bool, unsorted: bool) -> Result<()> {
+    if weighted {
+        if unsorted { encode::write_weighted_unsorted(target, edges, RUN) }
+        else        { encode::write_weighted(target, edges) }
+    }
+    else {
+        let mut pairs = edges.map(|(source, target, _)| (source, target));
+        if unsorted { encode::write_unsorted(target, &mut pairs, RUN) }
+        else        { encode::write(target, &mut pairs) }
+    }
+}
 
-    encode::write(&*target,&mut source).unwrap();
+// reports the outcome of encoding, and of reading the edges that were encoded
+fn report(target: &str, result: Result<()>, read: Result<usize>) {
+    match read {
+        Ok(skipped) => {
+            if skipped > 0 { println!("skipped {} malformed lines", skipped); }
+            if let Err(error) = result { eprintln!("error: {}", error); std::process::exit(1); }
+        },
+        Err(error) => {
+            // the graph holds only the edges before the error; make sure it is not loaded
+            let _ = std::fs::remove_file(format!("{}.meta", target));
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        },
+    }
 }
@@ -14,6 +14,10 @@ fn main () {
 
     let node_cnt = std::env::args().skip(1).next().unwrap().parse::<usize>().unwrap();
     let edge_cnt = std::env::args().skip(2).next().unwrap().parse::<usize>().unwrap();
+    let damping = std::env::args().skip(3).next()
+                                  .filter(|x| !x.starts_with('-'))  // timely options follow
+                                  .map(|x| x.parse::<f32>().unwrap())
+                                  .unwrap_or(0.85);
 
     timely::execute_from_args(std::env::args(), move |root| {
 
@@ -58,7 +62,7 @@ fn main () {
                     if iter.inner == 20 && index == 0 { println!("average: {}", (time::precise_time_s() - going) / 10.0 ); }
 
                     // prepare src for transmitting to destinations
-                    for s in 0..ranks.len() { ranks[s] = 0.15 + 0.85 * ranks[s] / degrs[s] as f32; }
+                    for s in 0..ranks.len() { ranks[s] = (1.0 - damping) + damping * ranks[s] / degrs[s] as f32; }
 
                     // wander through destinations
                     let mut session = output.session(&iter);
@@ -0,0 +1,19 @@
+extern crate pagerank;
+
+use pagerank::encode;
+use pagerank::graphmap::shard_name;
+
+// splits an encoded graph into one shard per worker, for `pagerank --sharded`;
+// shard i holds the edges of nodes i, i + workers, i + 2 * workers, and so on.
+
+fn main() {
+    println!("usage: shard <source> <workers>");
+    let source = std::env::args().skip(1).next().unwrap();
+    let peers: usize = std::env::args().skip(2).next().and_then(|x| x.parse().ok()).expect("malformed workers");
+    assert!(peers > 0, "workers must be positive");
+
+    match encode::write_shards(&source, peers) {
+        Ok(()) => println!("wrote {} .. {}", shard_name(&source, 0, peers), shard_name(&source, peers - 1, peers)),
+        Err(error) => { eprintln!("error: {}", error); std::process::exit(1); },
+    }
+}
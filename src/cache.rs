@@ -0,0 +1,110 @@
+use std::mem;
+use std::ops;
+use std::slice;
+
+use std::io::{ Read, Write, BufWriter };
+use std::fs::{ self, File };
+use std::time::UNIX_EPOCH;
+
+use typedrw::TypedMemoryMap;
+use graphmap::Node;
+use meta::Meta;
+use error::{ Result, InFile };
+
+/// Where `run` keeps each worker's transposed edges between runs.
+///
+/// Worker `index` of `peers` keeps its degrees, destinations and sources under
+/// `<prefix>-<index>-of-<peers>`, along with `key`; a later run with the same key
+/// and number of workers maps these files rather than exchanging and sorting edges.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    pub prefix: String,
+    pub key: String,        // identifies the graph, for example as by `key`
+}
+
+/// An identifier for the graph encoded under `prefix`, which changes whenever
+/// its files are written again.
+pub fn key(prefix: &str) -> Result<String> {
+    let meta = Meta::read(prefix)?;
+    let targets = format!("{}.targets", prefix);
+    let modified = fs::metadata(&targets).in_file(&targets)?.modified().in_file(&targets)?;
+    let since = modified.duration_since(UNIX_EPOCH).map(|x| (x.as_secs(), x.subsec_nanos())).unwrap_or((0, 0));
+    Ok(format!("{:?} modified {}.{:09}", meta, since.0, since.1))
+}
+
+/// Data either computed in memory or mapped from a file.
+pub enum Stored<T: Copy> {
+    Owned(Vec<T>),
+    Mapped(TypedMemoryMap<T>),
+}
+
+impl<T: Copy> ops::Deref for Stored<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        match *self {
+            Stored::Owned(ref vec) => &vec[..],
+            Stored::Mapped(ref map) => &map[..],
+        }
+    }
+}
+
+impl Cache {
+    fn name(&self, index: usize, peers: usize, extension: &str) -> String {
+        format!("{}-{}-of-{}.{}", self.prefix, index, peers, extension)
+    }
+
+    /// Maps the degrees, destinations, incoming edge counts and sources that
+    /// `transpose` produced for worker `index` of `peers`, or returns `None` if
+    /// they were not cached under this key.
+    pub fn load(&self, index: usize, peers: usize) -> Result<Option<(Stored<u32>, Stored<Node>, Stored<u32>, Stored<Node>)>> {
+        let key = self.name(index, peers, "key");
+        let mut found = String::new();
+        match File::open(&key) {
+            Ok(mut file) => { file.read_to_string(&mut found).in_file(&key)?; },
+            Err(_) => return Ok(None),
+        }
+        if found != self.key { return Ok(None); }
+
+        let deg = TypedMemoryMap::new(self.name(index, peers, "deg"))?;
+        let dst = TypedMemoryMap::new(self.name(index, peers, "dst"))?;
+        let cnt = TypedMemoryMap::new(self.name(index, peers, "cnt"))?;
+        let trn = TypedMemoryMap::new(self.name(index, peers, "trn"))?;
+        Ok(Some((Stored::Mapped(deg), Stored::Mapped(dst), Stored::Mapped(cnt), Stored::Mapped(trn))))
+    }
+
+    /// Writes the output of `transpose` for worker `index` of `peers`.
+    pub fn save(&self, index: usize, peers: usize, deg: &[u32], dst: &[Node], cnt: &[u32], trn: &[Node]) -> Result<()> {
+        // the key goes last, so that an interrupted save is never loaded
+        let key = self.name(index, peers, "key");
+        let _ = fs::remove_file(&key);
+        write_slice(&self.name(index, peers, "deg"), deg)?;
+        write_slice(&self.name(index, peers, "dst"), dst)?;
+        write_slice(&self.name(index, peers, "cnt"), cnt)?;
+        write_slice(&self.name(index, peers, "trn"), trn)?;
+        File::create(&key).and_then(|mut file| file.write_all(self.key.as_bytes())).in_file(&key)
+    }
+}
+
+// writes the bytes of `data`, to be mapped as a `TypedMemoryMap<T>`
+fn write_slice<T: Copy>(filename: &str, data: &[T]) -> Result<()> {
+    let bytes = unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>()) };
+    let mut writer = BufWriter::new(File::create(filename).in_file(filename)?);
+    writer.write_all(bytes).in_file(filename)?;
+    writer.flush().in_file(filename)
+}
+
+#[test]
+fn cache_round_trip() {
+    let directory = ::tempdir::TempDir::new("cache_round_trip").unwrap();
+    let prefix = directory.path().join("graph").to_string_lossy().into_owned();
+    let cache = Cache { prefix: prefix.clone(), key: "graph one".to_owned() };
+
+    assert!(cache.load(1, 2).unwrap().is_none());
+    cache.save(1, 2, &[2, 0, 1], &[1, 4], &[2, 1], &[0, 2, 0]).unwrap();
+    let (deg, dst, cnt, trn) = cache.load(1, 2).unwrap().unwrap();
+    assert_eq!((&deg[..], &dst[..], &cnt[..], &trn[..]), (&[2, 0, 1][..], &[1, 4][..], &[2, 1][..], &[0, 2, 0][..]));
+
+    // another graph, or another number of workers, misses
+    assert!(Cache { prefix: prefix.clone(), key: "graph two".to_owned() }.load(1, 2).unwrap().is_none());
+    assert!(cache.load(1, 3).unwrap().is_none());
+}
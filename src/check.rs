@@ -0,0 +1,167 @@
+use std::mem;
+use std::fs;
+use std::path::Path;
+
+use typedrw::TypedMemoryMap;
+use graphmap::Node;
+use meta::Meta;
+use error::{ Result, InFile };
+
+/// The findings of `check` on an encoded graph.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub nodes: usize,           // entries in `.offsets`
+    pub edges: usize,           // entries in `.targets`
+    pub errors: Vec<String>,    // problems that make the graph unusable
+    pub self_loops: usize,      // edges from a node to itself
+    pub duplicates: usize,      // edges repeating an earlier edge of the same node
+    pub unsorted: usize,        // nodes whose targets are not in increasing order
+}
+
+impl Report {
+    /// Indicates that no problem was found that makes the graph unusable.
+    pub fn valid(&self) -> bool { self.errors.is_empty() }
+}
+
+/// Checks the files of the graph encoded under `prefix`.
+///
+/// Verifies that file sizes are multiples of their element widths and agree with
+/// `<prefix>.meta`, that offsets never decrease and end at the number of edges,
+/// and that every target is a node. With `thorough`, also counts self-loops,
+/// duplicate edges and unsorted adjacency lists, which are legal but often
+/// unintended; duplicate edges are only counted within sorted lists.
+///
+/// Returns an error only if the files cannot be read at all.
+pub fn check(prefix: &str, thorough: bool) -> Result<Report> {
+
+    let mut report = Report::default();
+
+    let offsets = format!("{}.offsets", prefix);
+    let targets = format!("{}.targets", prefix);
+    let weights = format!("{}.weights", prefix);
+
+    let mut sized = true;
+    let offsets_size = file_size(&offsets, mem::size_of::<u64>(), &mut report, &mut sized)?;
+    let targets_size = file_size(&targets, mem::size_of::<Node>(), &mut report, &mut sized)?;
+    let weights_size = if Path::new(&weights).exists() { Some(file_size(&weights, mem::size_of::<f32>(), &mut report, &mut sized)?) }
+                       else                            { None };
+
+    report.nodes = offsets_size / mem::size_of::<u64>();
+    report.edges = targets_size / mem::size_of::<Node>();
+
+    // shards hold every peers-th node of a larger graph
+    let mut shard = None;
+    match Meta::read(prefix) {
+        Ok(meta) => {
+            shard = meta.shard;
+            if meta.nodes != report.nodes as u64 {
+                report.errors.push(format!("{}.meta lists {} nodes, {} holds {}", prefix, meta.nodes, offsets, report.nodes));
+            }
+            if meta.edges != report.edges as u64 {
+                report.errors.push(format!("{}.meta lists {} edges, {} holds {}", prefix, meta.edges, targets, report.edges));
+            }
+            if meta.weighted != weights_size.is_some() {
+                report.errors.push(format!("{}.meta and the presence of {} disagree", prefix, weights));
+            }
+        },
+        Err(error) => report.errors.push(format!("{}", error)),
+    }
+
+    if let Some(size) = weights_size {
+        if size / mem::size_of::<f32>() != report.edges {
+            report.errors.push(format!("{} holds {} weights for {} edges", weights, size / mem::size_of::<f32>(), report.edges));
+        }
+    }
+
+    // the contents can only be read if the sizes make sense
+    if !sized { return Ok(report); }
+
+    let nodes = TypedMemoryMap::<u64>::new(offsets.clone())?;
+    let edges = TypedMemoryMap::<Node>::new(targets.clone())?;
+    let nodes = &nodes[..];
+    let edges = &edges[..];
+
+    let mut decreasing = 0;
+    let mut start = 0;
+    for (node, &limit) in nodes.iter().enumerate() {
+        if limit < start {
+            if decreasing == 0 { report.errors.push(format!("{}: offset of node {} decreases, from {} to {}", offsets, node, start, limit)); }
+            decreasing += 1;
+        }
+        start = limit;
+    }
+    if decreasing > 1 { report.errors.push(format!("{}: {} offsets decrease in total", offsets, decreasing)); }
+
+    let last = nodes.last().map(|&x| x).unwrap_or(0);
+    if last != edges.len() as u64 {
+        report.errors.push(format!("{}: last offset is {}, but {} holds {} edges", offsets, last, targets, edges.len()));
+    }
+
+    let total = shard.map(|x| x.total as usize).unwrap_or(nodes.len());
+    let mut out_of_range = 0;
+    for (position, &target) in edges.iter().enumerate() {
+        if target as usize >= total {
+            if out_of_range == 0 { report.errors.push(format!("{}: edge {} targets {}, beyond the last node {}", targets, position, target, total as i64 - 1)); }
+            out_of_range += 1;
+        }
+    }
+    if out_of_range > 1 { report.errors.push(format!("{}: {} targets out of range in total", targets, out_of_range)); }
+
+    // adjacency lists can only be inspected with valid offsets
+    if thorough && decreasing == 0 && last <= edges.len() as u64 {
+        let mut start = 0;
+        for (node, &limit) in nodes.iter().enumerate() {
+            let list = &edges[start as usize .. limit as usize];
+            let node = shard.map(|x| node * x.peers as usize + x.index as usize).unwrap_or(node);
+            report.self_loops += list.iter().filter(|&&x| x as usize == node).count();
+            if list.windows(2).all(|x| x[0] <= x[1]) {
+                report.duplicates += list.windows(2).filter(|x| x[0] == x[1]).count();
+            }
+            else {
+                report.unsorted += 1;
+            }
+            start = limit;
+        }
+    }
+
+    Ok(report)
+}
+
+// the size of `filename`, noting in `report` if it is not a multiple of `width`
+fn file_size(filename: &str, width: usize, report: &mut Report, sized: &mut bool) -> Result<usize> {
+    let size = fs::metadata(filename).in_file(filename)?.len() as usize;
+    if size % width != 0 {
+        report.errors.push(format!("{}: size {} is not a multiple of {}", filename, size, width));
+        *sized = false;
+    }
+    Ok(size)
+}
+
+#[test]
+fn check_graphs() {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use encode;
+
+    let target = ::tempdir::TempDir::new("check_graphs").unwrap();
+    let files = target.path().to_string_lossy();
+
+    // a self-loop, a duplicate edge and an unsorted list
+    let data:&[(Node,Node)] = &[(0,0),(0,1),(1,2),(1,2),(2,3),(2,0)];
+    encode::write(&*files, &mut data.iter().cloned()).unwrap();
+    let report = check(&*files, true).unwrap();
+    assert!(report.valid());
+    assert_eq!((report.nodes, report.edges), (4, 6));
+    assert_eq!((report.self_loops, report.duplicates, report.unsorted), (1, 1, 1));
+
+    // an edge to a node beyond the last, and a mismatched .meta
+    let mut edges = OpenOptions::new().append(true).open(format!("{}.targets", files)).unwrap();
+    edges.write_all(&unsafe { mem::transmute::<_, [u8; mem::size_of::<Node>()]>(9 as Node) }).unwrap();
+    let report = check(&*files, false).unwrap();
+    assert_eq!(report.errors.len(), 3);
+
+    // a partial edge
+    edges.write_all(&[1]).unwrap();
+    let report = check(&*files, false).unwrap();
+    assert!(!report.valid());
+}
@@ -1,27 +1,64 @@
 use std::mem;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 
-use std::io::{ Result, BufWriter, Write };
-use std::fs::File;
+use std::io::{ self, ErrorKind, BufReader, BufWriter, Read, Write };
+use std::fs::{ self, File };
 
-pub fn write<I>(target:&str, pairs:&mut I) -> Result<()> where I:Iterator<Item=(u32,u32)> {
+use byteorder::{ NativeEndian, ReadBytesExt, WriteBytesExt };
 
-    let mut node_writer = BufWriter::new(File::create(format!("{}.offsets", target)).unwrap());
-    let mut edge_writer = BufWriter::new(File::create(format!("{}.targets", target)).unwrap());
+use error::{ Error, Result, InFile };
+use meta::{ Meta, Shard };
+use sorting::{ SegmentList, radix_sort_node };
+use graphmap::{ GraphMMap, Graph, WeightedGraph, Node, shard_name };
 
-    let mut cur_source = 0u32;
+// input must be sorted by source; see `write_unsorted` otherwise
+pub fn write<I>(target:&str, pairs:&mut I) -> Result<()> where I:Iterator<Item=(Node,Node)> {
+    encode(target, &mut pairs.map(|(source, target)| (source, target, 1.0)), false)
+}
+
+// as `write`, but also writes each edge's weight to `<target>.weights`
+pub fn write_weighted<I>(target:&str, triples:&mut I) -> Result<()> where I:Iterator<Item=(Node,Node,f32)> {
+    encode(target, triples, true)
+}
+
+fn encode<I>(target:&str, edges:&mut I, weighted: bool) -> Result<()> where I:Iterator<Item=(Node,Node,f32)> {
+
+    let node_file = format!("{}.offsets", target);
+    let edge_file = format!("{}.targets", target);
+    let weight_file = format!("{}.weights", target);
+
+    let mut node_writer = BufWriter::new(File::create(&node_file).in_file(&node_file)?);
+    let mut edge_writer = BufWriter::new(File::create(&edge_file).in_file(&edge_file)?);
+    let mut weight_writer = if weighted { Some(BufWriter::new(File::create(&weight_file).in_file(&weight_file)?)) }
+                            else        { None };
+
+    let mut cur_source: Node = 0;
     let mut cur_offset = 0u64;
-    let mut max_vertex = 0u32;
+    let mut max_vertex: Node = 0;
 
-    while let Some((source,target)) = pairs.next() {
+    while let Some((source,target,weight)) = edges.next() {
+        if source < cur_source {
+            let message = format!("edge {} is from node {}, after edges from node {}; input must be sorted by source", cur_offset, source, cur_source);
+            return Err(Error::Format(node_file, message));
+        }
+        // rank is split in proportion to weights, which must make sense as proportions
+        if weighted && !(weight >= 0.0 && weight.is_finite()) {
+            let message = format!("edge {} from node {} has weight {}; weights must be finite and not negative", cur_offset, source, weight);
+            return Err(Error::Format(weight_file, message));
+        }
         while cur_source < source {
-            node_writer.write(&unsafe { mem::transmute::<_, [u8; 8]>(cur_offset) }).unwrap();
+            node_writer.write_all(&unsafe { mem::transmute::<_, [u8; 8]>(cur_offset) }).in_file(&node_file)?;
             cur_source += 1;
         }
 
         max_vertex = ::std::cmp::max(max_vertex, source);
         max_vertex = ::std::cmp::max(max_vertex, target);
 
-        edge_writer.write(&unsafe { mem::transmute::<_, [u8; 4]>(target) }).unwrap();
+        edge_writer.write_all(&unsafe { mem::transmute::<_, [u8; mem::size_of::<Node>()]>(target) }).in_file(&edge_file)?;
+        if let Some(ref mut weight_writer) = weight_writer {
+            weight_writer.write_all(&unsafe { mem::transmute::<_, [u8; 4]>(weight) }).in_file(&weight_file)?;
+        }
         cur_offset += 1;
     }
 
@@ -29,9 +66,216 @@ pub fn write<I>(target:&str, pairs:&mut I) -> Result<()> where I:Iterator<Item=(
 
     // a bit of a waste. convenient.
     while cur_source <= max_vertex {
-        node_writer.write(&unsafe { mem::transmute::<_, [u8; 8]>(cur_offset) }).unwrap();
+        node_writer.write_all(&unsafe { mem::transmute::<_, [u8; 8]>(cur_offset) }).in_file(&node_file)?;
         cur_source += 1;
     };
 
+    // describe the data only once it is all written
+    node_writer.flush().in_file(&node_file)?;
+    edge_writer.flush().in_file(&edge_file)?;
+    if let Some(ref mut weight_writer) = weight_writer { weight_writer.flush().in_file(&weight_file)?; }
+
+    Meta { nodes: cur_source as u64, edges: cur_offset, weighted: weighted, shard: None }.write(target)
+}
+
+/// Splits the graph encoded under `prefix` into `peers` shards, one per worker.
+///
+/// Shard `index` is written under `shard_name(prefix, index, peers)`, and holds
+/// the edges of the nodes with `node % peers == index`, so that each worker need
+/// only store and map its own shard; see `GraphMMap::new_shard`.
+pub fn write_shards(prefix: &str, peers: usize) -> Result<()> {
+    let graph = GraphMMap::new(prefix)?;
+    if graph.shard().is_some() {
+        return Err(Error::Format(format!("{}.meta", prefix), "already a shard".to_owned()));
+    }
+    for index in 0..peers {
+        let target = shard_name(prefix, index, peers);
+        let node_file = format!("{}.offsets", target);
+        let edge_file = format!("{}.targets", target);
+        let weight_file = format!("{}.weights", target);
+
+        let mut node_writer = BufWriter::new(File::create(&node_file).in_file(&node_file)?);
+        let mut edge_writer = BufWriter::new(File::create(&edge_file).in_file(&edge_file)?);
+        let mut weight_writer = if graph.weighted() { Some(BufWriter::new(File::create(&weight_file).in_file(&weight_file)?)) }
+                                else                { None };
+
+        let mut nodes = 0u64;
+        let mut offset = 0u64;
+        for node in (index..graph.nodes()).step_by(peers) {
+            for &target in graph.edges(node) {
+                edge_writer.write_all(&unsafe { mem::transmute::<_, [u8; mem::size_of::<Node>()]>(target) }).in_file(&edge_file)?;
+            }
+            if let Some(ref mut weight_writer) = weight_writer {
+                for &weight in graph.weights(node) {
+                    weight_writer.write_all(&unsafe { mem::transmute::<_, [u8; 4]>(weight) }).in_file(&weight_file)?;
+                }
+            }
+            offset += graph.edges(node).len() as u64;
+            node_writer.write_all(&unsafe { mem::transmute::<_, [u8; 8]>(offset) }).in_file(&node_file)?;
+            nodes += 1;
+        }
+
+        node_writer.flush().in_file(&node_file)?;
+        edge_writer.flush().in_file(&edge_file)?;
+        if let Some(ref mut weight_writer) = weight_writer { weight_writer.flush().in_file(&weight_file)?; }
+
+        let shard = Shard { index: index as u64, peers: peers as u64, total: graph.nodes() as u64 };
+        Meta { nodes: nodes, edges: offset, weighted: graph.weighted(), shard: Some(shard) }.write(&target)?;
+    }
+    Ok(())
+}
+
+/// As `write`, but accepts edges in any order.
+///
+/// Sorts runs of up to `run` edges in memory and spills them to `<target>.run-<i>`
+/// files, which are then merged into the encoded graph and removed. Only one run
+/// is held in memory at a time, so inputs larger than memory can be encoded.
+pub fn write_unsorted<I>(target:&str, pairs:&mut I, run: usize) -> Result<()> where I:Iterator<Item=(Node,Node)> {
+    external(target, &mut pairs.map(|(source, target)| (source, target, 1.0)), run, false)
+}
+
+// as `write_unsorted`, but also writes each edge's weight to `<target>.weights`
+pub fn write_weighted_unsorted<I>(target:&str, triples:&mut I, run: usize) -> Result<()> where I:Iterator<Item=(Node,Node,f32)> {
+    external(target, triples, run, true)
+}
+
+fn external<I>(target:&str, edges:&mut I, run: usize, weighted: bool) -> Result<()> where I:Iterator<Item=(Node,Node,f32)> {
+    let mut names = Vec::new();
+    let result = spill(target, edges, run, weighted, &mut names).and_then(|_| merge(target, &names, weighted));
+    for name in &names { let _ = fs::remove_file(name); }
+    result
+}
+
+// writes sorted runs of `edges` to files, recording their names in `names`
+fn spill<I>(target:&str, edges:&mut I, run: usize, weighted: bool, names: &mut Vec<String>) -> Result<()> where I:Iterator<Item=(Node,Node,f32)> {
+    let mut free = Vec::new();
+    loop {
+        let mut segments = SegmentList::new(1 << 10);
+        segments.push(edges.by_ref().take(run));
+        let mut data = segments.finalize();
+        if data.len() == 0 { return Ok(()); }
+
+        // radix sort is stable: sort by target, then by source
+        radix_sort_node(&mut data, &mut free, &|x: &(Node,Node,f32)| x.1);
+        radix_sort_node(&mut data, &mut free, &|x: &(Node,Node,f32)| x.0);
+
+        let name = format!("{}.run-{}", target, names.len());
+        names.push(name.clone());
+        let mut writer = BufWriter::new(File::create(&name).in_file(&name)?);
+        for mut segment in data.drain(..) {
+            for &(source, target, weight) in &segment {
+                write_edge(&mut writer, source, target, weight, weighted).in_file(&name)?;
+            }
+            segment.clear();
+            free.push(segment);
+        }
+        writer.flush().in_file(&name)?;
+    }
+}
+
+/// Writes an edge in the native-endian form of spilled runs, with its weight if `weighted`.
+pub fn write_edge<W: Write>(writer: &mut W, source: Node, target: Node, weight: f32, weighted: bool) -> io::Result<()> {
+    writer.write_uint::<NativeEndian>(source as u64, mem::size_of::<Node>())?;
+    writer.write_uint::<NativeEndian>(target as u64, mem::size_of::<Node>())?;
+    if weighted { writer.write_f32::<NativeEndian>(weight)?; }
     Ok(())
 }
+
+/// Reads an edge written by `write_edge`, or `None` at the end of the input.
+pub fn read_edge<R: Read>(reader: &mut R, weighted: bool) -> io::Result<Option<(Node,Node,f32)>> {
+    let source = match reader.read_uint::<NativeEndian>(mem::size_of::<Node>()) {
+        Ok(source) => source as Node,
+        Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let target = reader.read_uint::<NativeEndian>(mem::size_of::<Node>())? as Node;
+    let weight = if weighted { reader.read_f32::<NativeEndian>()? } else { 1.0 };
+    Ok(Some((source, target, weight)))
+}
+
+// k-way merge of the sorted runs into the encoded graph
+fn merge(target:&str, names: &[String], weighted: bool) -> Result<()> {
+    let mut runs = Runs { names: names, readers: Vec::new(), weights: vec![0.0; names.len()], heap: BinaryHeap::new(), weighted: weighted, error: None };
+    for name in names {
+        runs.readers.push(BufReader::new(File::open(name).in_file(name)?));
+    }
+    for index in 0..names.len() { runs.advance(index); }
+
+    encode(target, &mut runs, weighted)?;
+    match runs.error {
+        // the graph is incomplete; make sure it is not loaded
+        Some(error) => { let _ = fs::remove_file(format!("{}.meta", target)); Err(error) },
+        None => Ok(()),
+    }
+}
+
+struct Runs<'a> {
+    names: &'a [String],
+    readers: Vec<BufReader<File>>,
+    weights: Vec<f32>,                          // weight of each run's edge in `heap`
+    heap: BinaryHeap<Reverse<(Node,Node,usize)>>, // next edge of each run, and the run
+    weighted: bool,
+    error: Option<Error>,                       // the first error reading a run
+}
+
+impl<'a> Runs<'a> {
+    // moves the next edge of run `index` into `heap`
+    fn advance(&mut self, index: usize) {
+        match read_edge(&mut self.readers[index], self.weighted) {
+            Ok(Some((source, target, weight))) => {
+                self.weights[index] = weight;
+                self.heap.push(Reverse((source, target, index)));
+            },
+            Ok(None) => { },
+            Err(error) => { self.error = Some(Error::Io(self.names[index].clone(), error)); },
+        }
+    }
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = (Node,Node,f32);
+    fn next(&mut self) -> Option<(Node,Node,f32)> {
+        if self.error.is_some() { return None; }
+        let Reverse((source, target, index)) = self.heap.pop()?;
+        let weight = self.weights[index];
+        self.advance(index);
+        Some((source, target, weight))
+    }
+}
+
+#[test]
+fn encode_unsorted() {
+    use graphmap::{ GraphMMap, Graph, WeightedGraph, edge_list };
+    let target = ::tempdir::TempDir::new("encode_unsorted").unwrap();
+    let files = target.path().to_string_lossy();
+
+    let data:&[(Node,Node,f32)] = &[(2,3,0.5),(0,2,1.0),(4,1,1.5),(0,1,2.0),(2,0,2.5),(1,4,3.0),(0,2,3.5)];
+    assert!(write(&*files, &mut data.iter().map(|&(s, t, _)| (s, t))).is_err());
+
+    // runs of two edges, so that several runs are merged
+    write_weighted_unsorted(&*files, &mut data.iter().cloned(), 2).unwrap();
+    let graph = GraphMMap::new(&*files).unwrap();
+    let mut sorted = data.to_vec();
+    sorted.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+    assert_eq!(edge_list(&graph, 0..graph.nodes()).collect::<Vec<_>>(), sorted.iter().map(|&(s, t, _)| (s, t)).collect::<Vec<_>>());
+    assert_eq!(graph.weights(0), &[2.0, 1.0, 3.5]);
+    assert!(!::std::path::Path::new(&format!("{}.run-0", files)).exists());
+}
+
+#[test]
+fn encode_shards() {
+    use graphmap::edge_list;
+    let target = ::tempdir::TempDir::new("encode_shards").unwrap();
+    let files = target.path().to_string_lossy();
+
+    let data:&[(Node,Node,f32)] = &[(0,1,0.5),(0,2,1.0),(1,4,1.5),(3,0,2.0),(4,1,2.5),(4,3,3.0)];
+    write_weighted(&*files, &mut data.iter().cloned()).unwrap();
+    write_shards(&*files, 2).unwrap();
+
+    let shards = vec![GraphMMap::new_shard(&*files, 0, 2).unwrap(), GraphMMap::new_shard(&*files, 1, 2).unwrap()];
+    assert_eq!(shards[1].nodes(), 5);
+    assert_eq!(edge_list(&shards[0], 0..5).collect::<Vec<_>>(), vec![(0,1),(0,2),(4,1),(4,3)]);
+    assert_eq!(edge_list(&shards[1], 0..5).collect::<Vec<_>>(), vec![(1,4),(3,0)]);
+    assert_eq!(shards[0].weights(4), &[2.5, 3.0]);
+    assert!(GraphMMap::new_shard(&*files, 0, 3).is_err());
+}
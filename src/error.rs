@@ -0,0 +1,52 @@
+use std::io;
+use std::fmt;
+use std::error;
+use std::result;
+
+/// Errors in reading or writing graph and rank files, naming the file involved.
+#[derive(Debug)]
+pub enum Error {
+    Io(String, io::Error),      // the file could not be opened, read, written or mapped
+    Format(String, String),     // the file's contents are not as expected
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    /// The file the error concerns.
+    pub fn filename(&self) -> &str {
+        match *self {
+            Error::Io(ref filename, _) => filename,
+            Error::Format(ref filename, _) => filename,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref filename, ref error) => write!(f, "{}: {}", filename, error),
+            Error::Format(ref filename, ref message) => write!(f, "{}: {}", filename, message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(_, ref error) => Some(error),
+            Error::Format(_, _) => None,
+        }
+    }
+}
+
+/// Attaches the name of the file involved to I/O errors.
+pub trait InFile<T> {
+    fn in_file(self, filename: &str) -> Result<T>;
+}
+
+impl<T> InFile<T> for io::Result<T> {
+    fn in_file(self, filename: &str) -> Result<T> {
+        self.map_err(|error| Error::Io(filename.to_owned(), error))
+    }
+}
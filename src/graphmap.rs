@@ -1,42 +1,139 @@
+use std::ops::Range;
+
 use typedrw::TypedMemoryMap;
+use meta::{ Meta, Shard };
+use error::{ Error, Result };
+
+/// Node identifiers: 32 bits wide, or 64 bits with the `ids64` feature.
+#[cfg(not(feature = "ids64"))]
+pub type Node = u32;
+#[cfg(feature = "ids64")]
+pub type Node = u64;
 
 pub trait Graph {
     fn nodes(&self) -> usize;
-    fn edges(&self, node: usize) -> &[u32];
+    fn edges(&self, node: usize) -> &[Node];
+}
+
+// a graph with an f32 weight for each edge, aligned with `edges(node)`
+pub trait WeightedGraph : Graph {
+    fn weights(&self, node: usize) -> &[f32];
 }
 
 pub struct GraphMMap {
     nodes: TypedMemoryMap<u64>,
-    edges: TypedMemoryMap<u32>,
+    edges: TypedMemoryMap<Node>,
+    weights: Option<TypedMemoryMap<f32>>,
+    shard: Option<Shard>,
+}
+
+/// The prefix of shard `index` of `peers` of the graph under `prefix`.
+pub fn shard_name(prefix: &str, index: usize, peers: usize) -> String {
+    format!("{}.shard-{}-of-{}", prefix, index, peers)
 }
 
 impl GraphMMap {
-    pub fn new(prefix: &str) -> GraphMMap {
-        GraphMMap {
-            nodes: TypedMemoryMap::new(format!("{}.offsets", prefix)),
-            edges: TypedMemoryMap::new(format!("{}.targets", prefix)),
+    // checks the files against <prefix>.meta before mapping them
+    pub fn new(prefix: &str) -> Result<GraphMMap> {
+        let meta = Meta::read(prefix)?;
+        meta.validate(prefix)?;
+        Ok(GraphMMap {
+            nodes: TypedMemoryMap::new(format!("{}.offsets", prefix))?,
+            edges: TypedMemoryMap::new(format!("{}.targets", prefix))?,
+            weights: if meta.weighted { Some(TypedMemoryMap::new(format!("{}.weights", prefix))?) } else { None },
+            shard: meta.shard,
+        })
+    }
+
+    /// Maps only worker `index`'s shard of the graph under `prefix`, as written
+    /// by `encode::write_shards` for `peers` workers.
+    ///
+    /// The shard reports every node of the whole graph, but only nodes with
+    /// `node % peers == index` have edges.
+    pub fn new_shard(prefix: &str, index: usize, peers: usize) -> Result<GraphMMap> {
+        let name = shard_name(prefix, index, peers);
+        let graph = GraphMMap::new(&name)?;
+        match graph.shard {
+            Some(shard) if shard.index == index as u64 && shard.peers == peers as u64 => Ok(graph),
+            _ => Err(Error::Format(format!("{}.meta", name), format!("not shard {} of {}", index, peers))),
         }
     }
-}
 
-impl Graph for GraphMMap {
-    fn nodes(&self) -> usize { self.nodes[..].len() }
-    fn edges(&self, node: usize) -> &[u32] {
+    /// Indicates whether the graph came with a `.weights` file.
+    pub fn weighted(&self) -> bool { self.weights.is_some() }
+
+    /// Describes the part of a larger graph this is, if it is a shard.
+    pub fn shard(&self) -> Option<Shard> { self.shard }
+
+    // the range of `edges` (and `weights`) holding node's edges
+    fn range(&self, node: usize) -> (usize, usize) {
+        // shards hold the edges of every peers-th node
+        let node = match self.shard {
+            Some(shard) if node as u64 % shard.peers != shard.index => return (0, 0),
+            Some(shard) => node / shard.peers as usize,
+            None => node,
+        };
         let nodes = &self.nodes[..];
         if node < nodes.len() {
             let start = if node==0 { 0 } else { nodes[node-1] } as usize;
             let limit = nodes[node] as usize;
-            &self.edges[..][start..limit]
+            (start, limit)
+        }
+        else { (0, 0) }
+    }
+}
+
+impl Graph for GraphMMap {
+    fn nodes(&self) -> usize {
+        match self.shard {
+            Some(shard) => shard.total as usize,
+            None => self.nodes[..].len(),
+        }
+    }
+    fn edges(&self, node: usize) -> &[Node] {
+        let (start, limit) = self.range(node);
+        &self.edges[..][start..limit]
+    }
+}
+
+impl WeightedGraph for GraphMMap {
+    fn weights(&self, node: usize) -> &[f32] {
+        let (start, limit) = self.range(node);
+        &self.weights.as_ref().expect("graph has no .weights file")[..][start..limit]
+    }
+}
+
+/// Iterates over the `(source, target)` edges of the nodes in `nodes`, in order.
+pub fn edge_list<'a, G: Graph>(graph: &'a G, nodes: Range<usize>) -> EdgeList<'a, G> {
+    let nodes = nodes.start .. ::std::cmp::min(nodes.end, graph.nodes());
+    EdgeList { graph: graph, nodes: nodes, node: 0, edges: &[] }
+}
+
+pub struct EdgeList<'a, G: Graph+'a> {
+    graph: &'a G,
+    nodes: Range<usize>,    // nodes whose edges are yet to come
+    node: usize,            // source of `edges`
+    edges: &'a [Node],      // remaining targets of `node`
+}
+
+impl<'a, G: Graph> Iterator for EdgeList<'a, G> {
+    type Item = (Node, Node);
+    fn next(&mut self) -> Option<(Node, Node)> {
+        while self.edges.is_empty() {
+            self.node = self.nodes.next()?;
+            self.edges = self.graph.edges(self.node);
         }
-        else { &[] }
+        let target = self.edges[0];
+        self.edges = &self.edges[1..];
+        Some((self.node as Node, target))
     }
 }
 
-pub struct MemoryGraph(Vec<Vec<u32>>);
+pub struct MemoryGraph(pub Vec<Vec<Node>>);
 
 impl Graph for MemoryGraph {
     fn nodes(&self) -> usize { self.0.len() }
-    fn edges(&self, node: usize) -> &[u32] {
+    fn edges(&self, node: usize) -> &[Node] {
         &*self.0[node]
     }
 }
@@ -47,12 +144,53 @@ fn encode_and_graphmap() {
     use encode;
     let target = ::tempdir::TempDir::new("encode_and_graphmap").unwrap();
     let files = target.path().to_string_lossy();
-    let data:&[(u32,u32)] = &[(0u32,1u32),(1,2),(2,3)];
+    let data:&[(Node,Node)] = &[(0,1),(1,2),(2,3)];
     encode::write(&*files, &mut data.iter().cloned()).unwrap();
-    let graph = GraphMMap::new(&*files);
+    let graph = GraphMMap::new(&*files).unwrap();
     assert_eq!(graph.nodes(), 4);
-    let read:Vec<(u32,u32)> = (0..graph.nodes()).flat_map(|src| {
-        graph.edges(src).into_iter().map(|d| (src as u32, *d)).collect::<Vec<(u32,u32)>>()
+    assert!(!graph.weighted());
+    let read:Vec<(Node,Node)> = (0..graph.nodes()).flat_map(|src| {
+        graph.edges(src).into_iter().map(|d| (src as Node, *d)).collect::<Vec<(Node,Node)>>()
     }).collect();
     assert_eq!(read, data);
+    assert_eq!(edge_list(&graph, 0..graph.nodes()).collect::<Vec<_>>(), data);
+    assert_eq!(edge_list(&graph, 1..3).collect::<Vec<_>>(), &data[1..]);
+    assert_eq!(Meta::read(&*files).unwrap(), Meta { nodes: 4, edges: 3, weighted: false, shard: None });
+}
+
+#[test]
+#[cfg(test)]
+fn graphmap_rejects_truncated() {
+    use std::fs::OpenOptions;
+    use encode;
+    let target = ::tempdir::TempDir::new("graphmap_rejects_truncated").unwrap();
+    let files = target.path().to_string_lossy();
+    let data:&[(Node,Node)] = &[(0,1),(1,2),(2,3)];
+    encode::write(&*files, &mut data.iter().cloned()).unwrap();
+    OpenOptions::new().write(true).open(format!("{}.targets", files)).unwrap().set_len(8).unwrap();
+    match GraphMMap::new(&*files) {
+        Err(error) => assert_eq!(error.filename(), format!("{}.targets", files)),
+        Ok(_) => panic!("truncated graph accepted"),
+    }
+}
+
+#[test]
+#[cfg(test)]
+fn encode_and_graphmap_weighted() {
+    use encode;
+    let target = ::tempdir::TempDir::new("encode_and_graphmap_weighted").unwrap();
+    let files = target.path().to_string_lossy();
+    let data:&[(Node,Node,f32)] = &[(0,1,0.5),(0,2,1.5),(2,3,2.0)];
+    encode::write_weighted(&*files, &mut data.iter().cloned()).unwrap();
+    let graph = GraphMMap::new(&*files).unwrap();
+    assert!(graph.weighted());
+    let read:Vec<(Node,Node,f32)> = (0..graph.nodes()).flat_map(|src| {
+        graph.edges(src).iter().zip(graph.weights(src)).map(|(d, w)| (src as Node, *d, *w)).collect::<Vec<_>>()
+    }).collect();
+    assert_eq!(read, data);
+
+    let nan:&[(Node,Node,f32)] = &[(0,1,0.5),(1,2,::std::f32::NAN)];
+    assert!(encode::write_weighted(&*files, &mut nan.iter().cloned()).is_err());
+    let negative:&[(Node,Node,f32)] = &[(0,1,-0.5)];
+    assert!(encode::write_weighted(&*files, &mut negative.iter().cloned()).is_err());
 }
@@ -0,0 +1,267 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem;
+
+use timely::progress::timestamp::RootTimestamp;
+use timely::dataflow::operators::*;
+use timely::dataflow::scopes::root::Root;
+use timely::dataflow::channels::pact::Exchange;
+use timely::drain::DrainExt;
+use timely_communication::Allocate;
+
+use graphmap::{Graph, Node};
+use sorting::{SegmentList, radix_sort_node};
+use partition::{Partition, Partitioning};
+use ranking::Ranking;
+use Config;
+
+/// The arrangement of `peers` workers in `rows` rows of `cols` workers each.
+///
+/// Worker `w` sits in row `w / cols` and column `w % cols`. Each node belongs
+/// to worker `node % peers`, and its edge to `dst` sits with the worker in the
+/// node's row and in `dst`'s column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grid {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Grid {
+    /// The squarest grid of `peers` workers, with no more rows than columns.
+    ///
+    /// A prime number of workers makes a single row, which is no better than `run`.
+    pub fn new(peers: usize) -> Grid {
+        let rows = (1..peers + 1).take_while(|r| r * r <= peers).filter(|r| peers % r == 0).last().unwrap_or(1);
+        Grid { rows: rows, cols: peers / rows }
+    }
+
+    fn peers(&self) -> usize { self.rows * self.cols }
+
+    /// The worker holding edges from `src` to `dst`.
+    pub fn block(&self, src: usize, dst: usize) -> usize {
+        (src % self.peers()) / self.cols * self.cols + (dst % self.peers()) % self.cols
+    }
+
+    // numbers the nodes owned within `src`'s row densely, as the workers of the row see them
+    fn slot(&self, src: usize) -> usize {
+        (src / self.peers()) * self.cols + (src % self.peers()) % self.cols
+    }
+}
+
+/// Runs PageRank over `graph` with edges partitioned in two dimensions.
+///
+/// Workers form a `Grid`, each holding the edges between the nodes of its row
+/// and those of its column. Every iteration each node's share of rank is sent
+/// along its row, and partial sums for each node go back along its column, so
+/// that each worker exchanges ranks of some `nodes / sqrt(peers)` nodes rather
+/// than up to `nodes` with `run`. This pays off for graphs whose high-degree
+/// nodes have edges to much of the graph.
+///
+/// Behaves as `run` otherwise, and returns the same shard of ranks; nodes are
+/// owned as by `Partitioning::Modulo`, and `config.use_process_local`,
+/// `config.cache` and `config.hubs` must not be set.
+pub fn run_2d<G:Graph,A:Allocate>(root:&mut Root<A>, graph:&G, config:&Config) -> Vec<(Node, f32)> {
+
+    let index = root.index() as usize;
+    let peers = root.peers() as usize;
+    assert!(config.partition == Partitioning::Modulo, "2D partitioning owns nodes by modulo");
+    assert!(!config.use_process_local, "2D partitioning does not aggregate by process");
+    assert!(config.cache.is_none(), "2D partitioning does not cache transposed edges");
+    assert!(config.hubs.is_empty(), "2D partitioning does not split hubs");
+
+    let iterations = config.iterations;
+    let damping = config.damping;
+
+    let nodes = graph.nodes();
+    let partition = Partition::modulo(nodes, peers);
+    let local = partition.local(index);
+    let grid = Grid::new(peers);
+    let row = index / grid.cols;
+    let mut ranking = Ranking::new(config, &partition, index, &[config.teleport.clone()]);
+    let control = ranking.control();
+
+    if index == 0 { println!("grid: {} rows of {} workers", grid.rows, grid.cols); }
+
+    let mut segments = SegmentList::new(1024);
+
+    // as in `run`, except that the owner of each node keeps its ranks and degree,
+    // while the workers of its row keep the edges from it.
+    // xs: shares of rank of the row's nodes, indexed by `grid.slot`
+    let mut deg = vec![0u32; partition.slots()];
+    let mut rev = vec![];
+    let mut trn = vec![];
+    let mut xs = vec![0.0f32; (nodes / peers + 1) * grid.cols];
+
+    for s in 0..local {
+        deg[s] = graph.edges(partition.node(index, s)).len() as u32;
+    }
+
+    let result = Rc::new(RefCell::new(Vec::new()));
+    let shard = result.clone();
+    let owned = partition.clone();
+
+    let mut input = root.scoped(|builder| {
+
+        let (input, edges) = builder.new_input::<(Node, Node)>();
+        let (cycle, sums) = builder.loop_variable::<(Node, f32)>(iterations, 1);
+
+        // owners of nodes: sum ranks, and send each node's share along its row.
+        // shares go out as (worker, node, share), aggregates as for `run`.
+        let shares = sums.unary_notify(
+                            Exchange::new(|x: &(Node,f32)| x.0 as u64), // ranks and aggregates
+                            "pagerank-owner",
+                            vec![RootTimestamp::new(0)],
+                            move |input, output, notificator| {
+
+            while let Some((iter, data)) = input.next() {
+                notificator.notify_at(&iter);
+                for &(node, rank) in data.iter() {
+                    let node = node as usize;
+                    if node < control { ranking.add(node / peers, &[rank]); }
+                    else              { ranking.receive(node, &[rank]); }
+                }
+            }
+
+            while let Some((iter, _)) = notificator.next() {
+
+                if index == 0 { println!("starting iteration {}", iter.inner); }
+
+                if ranking.start(iter.inner) {
+                    shard.borrow_mut().extend(ranking.shard(&owned));
+                    continue;
+                }
+
+                let mut next = iter.clone();
+                next.inner += 1;
+                notificator.notify_at(&next);
+
+                let mut session = output.session(&iter);
+                for (id, values) in ranking.aggregates(iter.inner, |s| deg[s] == 0) {
+                    session.give(((id as usize % peers) as u64, id, values[0]));
+                }
+
+                // only nodes with edges have shares to send
+                for s in 0..local {
+                    if deg[s] > 0 {
+                        let share = damping * ranking.src[s] / deg[s] as f32;
+                        for col in 0..grid.cols {
+                            session.give(((row * grid.cols + col) as u64, (s * peers + index) as Node, share));
+                        }
+                    }
+                }
+
+                ranking.reset();
+            }
+        });
+
+        // holders of edge blocks: sum shares by destination, and send sums to the owners
+        let sums = edges.binary_notify(&shares,
+                            Exchange::new(move |x: &(Node,Node)| grid.block(x.0 as usize, x.1 as usize) as u64),
+                            Exchange::new(|x: &(u64,Node,f32)| x.0),
+                            "pagerank-block",
+                            vec![],
+                            move |input1, input2, output, notificator| {
+
+            while let Some((_iter, data)) = input1.next() {
+                segments.push(data.drain_temp());
+            }
+
+            // aggregates pass straight through to their worker
+            while let Some((iter, data)) = input2.next() {
+                notificator.notify_at(&iter);
+                let mut session = output.session(&iter);
+                for &(_, node, share) in data.iter() {
+                    if (node as usize) < control { xs[grid.slot(node as usize)] = share; }
+                    else                         { session.give((node, share)); }
+                }
+            }
+
+            while let Some((iter, _)) = notificator.next() {
+                if iter.inner == 0 {
+                    let (a, b) = transpose_block(segments.finalize(), &grid);
+                    rev = a; trn = b;
+                    println!("worker: {} dst: {}, edges: {}", index, rev.len(), trn.len());
+                }
+
+                let mut session = output.session(&iter);
+                let mut trn_slice = &trn[..];
+                for &(dst, deg) in &rev {
+                    let mut accum = 0.0;
+                    for &s in &trn_slice[..deg as usize] {
+                        unsafe { accum += *xs.get_unchecked(s as usize); }
+                    }
+                    trn_slice = &trn_slice[deg as usize..];
+                    session.give((dst, accum));
+                }
+            }
+        });
+
+        sums.connect_loop(cycle);
+
+        input
+    });
+
+    for s in 0..local {
+        let node = partition.node(index, s);
+        for dst in graph.edges(node) {
+            input.send((node as Node, *dst));
+        }
+    }
+    input.close();
+    while root.step() { };
+
+    let shard = mem::replace(&mut *result.borrow_mut(), Vec::new());
+    shard
+}
+
+// returns (dst, deg) pairs, and a list of endpoints by their slot in the row
+fn transpose_block(mut edges: Vec<Vec<(Node, Node)>>, grid: &Grid) -> (Vec<(Node, u32)>, Vec<Node>) {
+
+    radix_sort_node(&mut edges, &mut Vec::new(), &|&(_,d)| d);
+
+    let mut rev = Vec::<(Node,u32)>::new();
+    let mut trn = Vec::with_capacity(edges.len() * 1024);
+    for list in edges {
+        for (s,d) in list {
+            if (rev.len() == 0) || (rev[rev.len()-1].0 < d) {
+                rev.push((d, 0u32));
+            }
+
+            let len = rev.len();
+            rev[len-1].1 += 1;
+            trn.push(grid.slot(s as usize) as Node);
+        }
+    }
+
+    (rev, trn)
+}
+
+#[test]
+fn test_grid() {
+    assert_eq!(Grid::new(1), Grid { rows: 1, cols: 1 });
+    assert_eq!(Grid::new(6), Grid { rows: 2, cols: 3 });
+    assert_eq!(Grid::new(16), Grid { rows: 4, cols: 4 });
+    assert_eq!(Grid::new(7), Grid { rows: 1, cols: 7 });
+
+    // four workers in two rows: node n is owned by worker n % 4
+    let grid = Grid::new(4);
+    assert_eq!(grid.block(5, 2), 0);    // row of worker 1, column of worker 2
+    assert_eq!(grid.block(6, 3), 3);    // row of worker 2, column of worker 3
+
+    // nodes 0, 1, 4 and 5 are those of the first row
+    let (rev, trn) = transpose_block(vec!(vec!((4,2),(0,2),(5,0),(1,6))), &grid);
+    assert_eq!(*rev, [(0,1),(2,2),(6,1)]);
+    assert_eq!(*trn, [3,2,0,1]);
+}
+
+#[test]
+fn grid_matches_run() {
+    use {test_ranks, assert_ranks, Job};
+
+    let mut config = Config::new();
+    config.iterations = 10;
+    for &workers in &[4, 6] {
+        let ranks = test_ranks(workers, vec![Job::Run(config.clone()), Job::Grid(config.clone())]);
+        assert_ranks(&ranks[1], &ranks[0]);
+    }
+}
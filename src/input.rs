@@ -0,0 +1,101 @@
+use std::io::{ self, BufRead, BufReader };
+use std::fs::File;
+
+use flate2::bufread::MultiGzDecoder;
+use bzip2::bufread::MultiBzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use error::{ Result, InFile };
+
+/// How an input file is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Plain,
+    Gzip,       // `.gz`
+    Bzip2,      // `.bz2`
+    Zstd,       // `.zst`
+}
+
+impl Compression {
+    /// Recognizes compressed data by its first bytes.
+    pub fn from_magic(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&[0x1f, 0x8b]) { Some(Compression::Gzip) }
+        else if bytes.starts_with(b"BZh") { Some(Compression::Bzip2) }
+        else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) { Some(Compression::Zstd) }
+        else { None }
+    }
+
+    /// Guesses the compression of a file from its name.
+    pub fn from_extension(filename: &str) -> Compression {
+        if filename.ends_with(".gz") { Compression::Gzip }
+        else if filename.ends_with(".bz2") { Compression::Bzip2 }
+        else if filename.ends_with(".zst") { Compression::Zstd }
+        else { Compression::Plain }
+    }
+}
+
+/// Opens `filename` for reading, decompressing it as it is read.
+///
+/// The compression is detected from the first bytes of the file, or failing
+/// that from its extension, so that misnamed files are read correctly.
+pub fn open(filename: &str) -> Result<Box<dyn BufRead>> {
+    let file = File::open(filename).in_file(filename)?;
+    decompress(BufReader::new(file), filename)
+}
+
+/// Opens standard input for reading, decompressing it if its first bytes say so.
+pub fn stdin() -> Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(io::stdin()), "<stdin>")
+}
+
+/// Opens `filename`, or standard input if there is none.
+pub fn open_or_stdin(filename: Option<&str>) -> Result<Box<dyn BufRead>> {
+    match filename {
+        Some(filename) => open(filename),
+        None => stdin(),
+    }
+}
+
+/// Detects how `filename` is compressed, as `open` does.
+pub fn compression(filename: &str) -> Result<Compression> {
+    let mut reader = BufReader::new(File::open(filename).in_file(filename)?);
+    let bytes = reader.fill_buf().in_file(filename)?;
+    Ok(Compression::from_magic(bytes).unwrap_or_else(|| Compression::from_extension(filename)))
+}
+
+fn decompress<R: BufRead+'static>(mut reader: R, filename: &str) -> Result<Box<dyn BufRead>> {
+    let compression = {
+        let bytes = reader.fill_buf().in_file(filename)?;
+        Compression::from_magic(bytes).unwrap_or_else(|| Compression::from_extension(filename))
+    };
+    Ok(match compression {
+        Compression::Plain => Box::new(reader),
+        Compression::Gzip  => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Compression::Zstd  => Box::new(BufReader::new(ZstdDecoder::with_buffer(reader).in_file(filename)?)),
+    })
+}
+
+#[test]
+fn open_compressed() {
+    use std::io::{ Read, Write };
+    use flate2::{ write::GzEncoder, Compression as Level };
+
+    let directory = ::tempdir::TempDir::new("open_compressed").unwrap();
+    let text = "0\t1\n1\t2\n";
+
+    // misnamed, to check that the magic bytes win
+    let filename = directory.path().join("edges.txt").to_string_lossy().into_owned();
+    let mut encoder = GzEncoder::new(File::create(&filename).unwrap(), Level::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let mut read = String::new();
+    open(&filename).unwrap().read_to_string(&mut read).unwrap();
+    assert_eq!(read, text);
+
+    let zstd = ::zstd::encode_all(text.as_bytes(), 0).unwrap();
+    let mut read = String::new();
+    decompress(io::Cursor::new(zstd), "edges").unwrap().read_to_string(&mut read).unwrap();
+    assert_eq!(read, text);
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use std::io::{ BufRead, BufReader, BufWriter, Write };
+use std::fs::File;
+
+use error::{ Result, InFile };
+use graphmap::Node;
+
+// file format of `<prefix>.labels`: one line per node, in order of node, holding
+// the label the node was read as.
+
+/// Assigns dense node identifiers to labels, in order of first appearance.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    ids: HashMap<String, Node>,
+    labels: Vec<String>,        // label of each assigned identifier
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary { Dictionary::default() }
+
+    /// The identifier of `label`, assigning the next one if it is new.
+    pub fn id(&mut self, label: &str) -> Node {
+        if let Some(&id) = self.ids.get(label) { return id; }
+        let id = self.labels.len() as Node;
+        assert!(id < Node::max_value(), "more labels than node identifiers");
+        self.ids.insert(label.to_owned(), id);
+        self.labels.push(label.to_owned());
+        id
+    }
+
+    /// The labels of the assigned identifiers, in order.
+    pub fn labels(&self) -> &[String] { &self.labels }
+
+    /// Writes `<prefix>.labels`.
+    pub fn write(&self, prefix: &str) -> Result<()> {
+        let filename = format!("{}.labels", prefix);
+        let mut writer = BufWriter::new(File::create(&filename).in_file(&filename)?);
+        for label in &self.labels {
+            writeln!(writer, "{}", label).in_file(&filename)?;
+        }
+        writer.flush().in_file(&filename)
+    }
+}
+
+/// Reads `<prefix>.labels`; entry `i` is the label of node `i`.
+pub fn read(prefix: &str) -> Result<Vec<String>> {
+    let filename = format!("{}.labels", prefix);
+    BufReader::new(File::open(&filename).in_file(&filename)?).lines().collect::<::std::io::Result<_>>().in_file(&filename)
+}
+
+#[test]
+fn labels_round_trip() {
+    let target = ::tempdir::TempDir::new("labels_round_trip").unwrap();
+    let prefix = target.path().join("graph").to_string_lossy().into_owned();
+
+    let mut dictionary = Dictionary::new();
+    let ids: Vec<Node> = ["http://a/", "http://b/", "http://a/", "carol"].iter().map(|x| dictionary.id(x)).collect();
+    assert_eq!(ids, vec![0, 1, 0, 2]);
+    dictionary.write(&prefix).unwrap();
+    assert_eq!(read(&prefix).unwrap(), dictionary.labels());
+}
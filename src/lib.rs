@@ -2,59 +2,224 @@ extern crate memmap;
 extern crate time;
 extern crate timely;
 extern crate getopts;
+extern crate byteorder;
+extern crate flate2;
+extern crate bzip2;
+extern crate zstd;
 #[cfg(test)]
 extern crate tempdir;
 extern crate timely_communication;
 
+pub mod error;
 pub mod typedrw;
 pub mod graphmap;
 pub mod sorting;
 pub mod encode;
-
+pub mod meta;
+pub mod teleport;
+pub mod batch;
+pub mod weighted;
+pub mod output;
+pub mod topk;
+pub mod check;
+pub mod text;
+pub mod input;
+pub mod labels;
+pub mod cache;
+pub mod partition;
+pub mod grid;
+mod ranking;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem;
 
 use timely::progress::timestamp::RootTimestamp;
 // use timely::progress::nested::Summary::Local;
 use timely::dataflow::*;
 use timely::dataflow::operators::*;
+use timely::dataflow::operators::Partition as Split;    // not to be confused with partition::Partition
 use timely::dataflow::scopes::root::Root;
 use timely::dataflow::channels::pact::Exchange;
 use timely::drain::DrainExt;
 use timely_communication::Allocate;
 
-use graphmap::Graph;
-use sorting::{SegmentList, radix_sort_32};
+use graphmap::{Graph, Node};
+use teleport::Teleport;
+use sorting::{SegmentList, radix_sort_node};
+use cache::{Cache, Stored};
+use partition::{Partition, Partitioning};
+use output::RankFile;
+use ranking::{Ranking, Ranks, AGGREGATES};
+
+/// Parameters of a PageRank computation.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub workers: usize,             // workers per process
+    pub use_process_local: bool,    // aggregate ranks within each process first
+    pub iterations: usize,          // maximum number of iterations
+    pub tolerance: Option<f32>,     // stop once the L1 change in ranks drops below this
+    pub damping: f32,               // probability of following an edge rather than teleporting, below 1
+    pub dangling: Dangling,         // where the rank of nodes without out-edges goes
+    pub teleport: Teleport,         // where teleporting surfers land
+    pub initial: Option<RankFile>,  // merged binary rank file to start from, rather than teleport mass
+    pub cache: Option<Cache>,       // where to keep transposed edges between runs
+    pub partition: Partitioning,    // how nodes are assigned to workers
+    pub hubs: Vec<Node>,            // nodes whose in-edges are summed in two stages, in order
+}
+
+/// How the rank held by nodes without out-edges is passed on each iteration.
+///
+/// Either way the mass is collected across all workers and handed out again
+/// with the damping factor applied, so that no rank leaks from the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dangling {
+    Uniform,    // spread evenly over all nodes
+    Teleport,   // spread in proportion to each node's teleport mass
+}
 
-pub fn run<G:Graph,A:Allocate>(workers:usize, root:&mut Root<A>, graph:&G, use_process_local:bool, iterations:usize) {
+impl Config {
+    /// Twenty iterations with damping 0.85 and uniform teleportation, and worker-level
+    /// aggregation on a single worker.
+    pub fn new() -> Config {
+        Config {
+            workers: 1,
+            use_process_local: false,
+            iterations: 20,
+            tolerance: None,
+            damping: 0.85,
+            dangling: Dangling::Uniform,
+            teleport: Teleport::Uniform,
+            initial: None,
+            cache: None,
+            partition: Partitioning::Modulo,
+            hubs: vec![],
+        }
+    }
+}
+
+/// Runs PageRank over `graph` as configured by `config`.
+///
+/// Iterates `config.iterations` times, or, if `config.tolerance` is set, until
+/// the L1 change in ranks summed across all workers falls below the tolerance.
+///
+/// Ranks are scaled so that the teleport mass of all nodes totals `1 - damping`
+/// per node; with `Teleport::Weights` that mass goes only to the listed nodes,
+/// which gives personalized PageRank.
+///
+/// With `config.initial`, ranks start from a merged binary rank file written by
+/// an earlier run, as by `output::merge`, so that a computation over a slightly
+/// changed graph converges quickly. Nodes the file does not cover start from
+/// their teleport mass.
+///
+/// With `config.cache`, each worker saves its transposed edges after the first
+/// run, and later runs with the same cache key and number of workers map them
+/// instead of exchanging and sorting the edges again.
+///
+/// Nodes, and the edges from them, are spread over workers as `config.partition`
+/// says, by default with node `n` on worker `n % peers`.
+///
+/// With `config.hubs`, the partial sums for those nodes go first to a worker of
+/// the sender's process, chosen by node, which combines them before sending one
+/// sum on to the node's owner, so that no worker adds up a partial sum from every
+/// other. Every worker must be given the same hubs, as found by `hubs`, and every
+/// process the same number of workers, `config.workers`.
+///
+/// Returns this worker's shard of the final ranks, as `(node, rank)` pairs for
+/// the nodes the partition assigns it, in increasing order of node.
+pub fn run<G:Graph,A:Allocate>(root:&mut Root<A>, graph:&G, config:&Config) -> Vec<(Node, f32)> {
+    compute(root, graph, config, &[config.teleport.clone()])
+}
+
+/// The nodes of `graph` with more than `degree` in-edges, in order, for `Config::hubs`.
+///
+/// Counts the in-edges of every node, so needs the whole graph rather than a
+/// shard; a process should call it once and hand the result to all its workers.
+pub fn hubs<G:Graph>(graph:&G, degree:usize) -> Vec<Node> {
+    let mut counts = vec![0u32; graph.nodes()];
+    for node in 0..graph.nodes() {
+        for &dst in graph.edges(node) { counts[dst as usize] += 1; }
+    }
+    (0..graph.nodes()).filter(|&node| counts[node] as usize > degree).map(|node| node as Node).collect()
+}
+
+// the dataflow of `run` and `batch::run_batch`: one computation for each of
+// `sources`, whose messages carry a rank of each node for every computation
+fn compute<G:Graph,A:Allocate,R:Ranks>(root:&mut Root<A>, graph:&G, config:&Config, sources:&[Teleport]) -> Vec<(Node, R)> {
 
     let index = root.index() as usize;
     let peers = root.peers() as usize;
 
+    let workers = config.workers;
+    let iterations = config.iterations;
+    let damping = config.damping;
+
     let start = time::precise_time_s();
 
     let nodes = graph.nodes();
+    let partition = Partition::new(config.partition, graph, peers);
+    let local = partition.local(index);     // nodes owned here
+
+    // ranks are sharded by the partition, and indexed by slot
+    let mut ranking = Ranking::new(config, &partition, index, sources);
+    let control = ranking.control();
+    let batch = ranking.batch;
 
     let mut segments = SegmentList::new(1024); // list of edge segments
 
-    // src is sharded naturally by node identifier
-    // edges are sharded by source only
-    // deg: vec of outgoing edges count, indexed by node/peers
-    // rev: list of (dst, incoming edges count)
-    // trn: source of edges, each one divided by peers count
-    let mut src = vec![];   // holds sharded ranks
-    let mut deg = vec![];   // holds sharded source degrees
-    let mut rev = vec![];   // holds (dst, deg) pairs
-    let mut trn = vec![];   // holds transposed sources
+    // edges are sharded by source
+    // deg: vec of outgoing edges count, indexed by slot
+    // dst, cnt: destinations of edges, and incoming edges count of each
+    // trn: source of edges, as slots
+    let mut deg = Stored::Owned(vec![]);    // holds sharded source degrees
+    let mut dst = Stored::Owned(vec![]);    // holds destinations
+    let mut cnt = Stored::Owned(vec![]);    // holds incoming edge counts
+    let mut trn = Stored::Owned(vec![]);    // holds transposed sources
+
+    // hubs are known by their position in the list, so every worker needs the same one,
+    // and each process must have `workers` workers for the combiners to be its own
+    let hubs = &config.hubs;
+    assert!(hubs.windows(2).all(|x| x[0] < x[1]) && hubs.iter().all(|&node| (node as usize) < nodes), "hubs must be nodes, in increasing order");
+    assert!(hubs.is_empty() || peers % workers == 0, "hubs need config.workers to divide the number of workers");
+    assert!(control + (AGGREGATES * peers + hubs.len()) * peers <= Node::max_value() as usize, "too many hubs");
+
+    // beyond the aggregates, `AGGREGATES * peers + h` in place of `k * peers + v`
+    // carries partial sums for hub `h` to the worker of this process combining them
+    let first = control + AGGREGATES * peers * peers;
+    let combiner = |h: usize| workers * (index / workers) + h % workers;
+    let hub_ids: Vec<(Node, Node)> = hubs.iter().enumerate()
+                                         .map(|(h, &node)| (node, (first + h * peers + combiner(h)) as Node))
+                                         .collect();
+
+    // transposed edges depend on the partition as much as on the graph;
+    // a stale or unreadable cache is only a missed shortcut, as the edges are still there
+    let cache = config.cache.clone().map(|mut cache| { cache.key = format!("{} {:?}", cache.key, config.partition); cache });
+    let mut cached = cache.as_ref().and_then(|cache| cache.load(index, peers).unwrap_or_else(|error| {
+        eprintln!("worker {}: ignoring cache: {}", index, error);
+        None
+    }));
+    let send_edges = cached.is_none();
 
     let mut going = start;
 
+    // final ranks, filled in by the operator at the last iteration
+    let result = Rc::new(RefCell::new(Vec::new()));
+    let shard = result.clone();
+
     let mut input = root.scoped(|builder| {
 
-        let (input, edges) = builder.new_input::<(u32, u32)>();
-        let (cycle, ranks) = builder.loop_variable::<(u32, f32)>(iterations, 1);
+        let (input, edges) = builder.new_input::<(Node, Node)>();
+        let (cycle, ranks) = builder.loop_variable::<(Node, R)>(iterations, 1);
+
+        let owner = partition.clone();
+        let route = partition.clone();
+        let layout = partition.clone();
 
         let mut ranks = edges.binary_notify(&ranks,
-                            Exchange::new(|x: &(u32,u32)| x.0 as u64), // edges are hashed by source
-                            Exchange::new(|x: &(u32,f32)| x.0 as u64), // ranks
+                            Exchange::new(move |x: &(Node,Node)| owner.owner(x.0 as usize) as u64), // edges go with their source
+                            Exchange::new(move |x: &(Node,R)| {     // ranks to their owner, aggregates by identifier
+                                if (x.0 as usize) < control { route.owner(x.0 as usize) as u64 } else { x.0 as u64 }
+                            }),
                             "pagerank",
                             vec![RootTimestamp::new(0)],
                             move |input1, input2, output, notificator| {
@@ -73,81 +238,137 @@ pub fn run<G:Graph,A:Allocate>(workers:usize, root:&mut Root<A>, graph:&G, use_p
                 // if the very first iteration, prepare some stuff.
                 // specifically, transpose edges and sort by destination.
                 if iter.inner == 0 {
-                    let segs = segments.finalize();
-                    let (a, b, c) = transpose(segs, peers, nodes);
-                    deg = a; rev = b; trn = c;
-                    src = vec![0.15f32; deg.len()];
+                    match cached.take() {
+                        Some((a, b, c, d)) => { deg = a; dst = b; cnt = c; trn = d; },
+                        None => {
+                            let segs = segments.finalize();
+                            let (a, rev, d) = transpose(segs, &layout);
+                            let b: Vec<Node> = rev.iter().map(|x| x.0).collect();
+                            let c: Vec<u32> = rev.iter().map(|x| x.1).collect();
+                            if let Some(ref cache) = cache {
+                                if let Err(error) = cache.save(index, peers, &a, &b, &c, &d) {
+                                    eprintln!("worker {}: not caching edges: {}", index, error);
+                                }
+                            }
+                            deg = Stored::Owned(a); dst = Stored::Owned(b); cnt = Stored::Owned(c); trn = Stored::Owned(d);
+                        },
+                    }
                 }
 
+                // start from the teleport mass, or hand out last iteration's dangling rank
+                let done = ranking.start(iter.inner);
+
                 // record some timings in order to estimate per-iteration times
-                if iter.inner == 0  { println!("worker: {} src: {}, dst: {}, edges: {}", index, src.len(), rev.len(), trn.len()); }
+                if iter.inner == 0  { println!("worker: {} src: {}, dst: {}, edges: {}", index, ranking.src.len() / batch, dst.len(), trn.len()); }
                 if iter.inner == 10 && index == 0 { going = time::precise_time_s(); }
                 if iter.inner == 20 && index == 0 { println!("average: {}", (time::precise_time_s() - going) / 10.0 ); }
 
-/*
-                if iter.inner == iterations {
-                    for (ix,x) in src.iter().enumerate() {
-                        println!("{}\t{:0.3}", ix*peers+index, x);
-                    }
+                // src holds the final ranks; hand them back rather than propagate
+                if done {
+                    shard.borrow_mut().extend(ranking.shard(&layout));
+                    continue;
+                }
+
+                // make sure we hear about the next iteration, even if no ranks arrive
+                let mut next = iter.clone();
+                next.inner += 1;
+                notificator.notify_at(&next);
+
+                let mut session = output.session(&iter);
+                for (id, values) in ranking.aggregates(iter.inner, |s| deg[s] == 0) {
+                    session.give((id, R::from_slice(&values)));
                 }
-*/
+
                 // from here on, src hold the amount to propagate on each edge
-                for s in 0..src.len() { src[s] = 0.85 * src[s] / deg[s] as f32; }
+                for s in 0..deg.len() {
+                    for x in &mut ranking.src[s * batch .. (s + 1) * batch] {
+                        *x = if deg[s] > 0 { damping * *x / deg[s] as f32 } else { 0.0 };
+                    }
+                }
 
-                // wander through destinations
+                // wander through destinations, sending sums for hubs under their identifiers
+                let src = &ranking.src;
                 let mut trn_slice = &trn[..];
-                let mut session = output.session(&iter);
-                for &(dst, deg) in &rev {
-                    let mut accum = 0.0;
-                    for &s in &trn_slice[..deg as usize] {
-                        // accum += src[s as usize];
-                        unsafe { accum += *src.get_unchecked(s as usize); }
+                let mut hub_slice = &hub_ids[..];
+                let mut name = |dst: Node| {
+                    while hub_slice.len() > 0 && hub_slice[0].0 < dst { hub_slice = &hub_slice[1..]; }
+                    if hub_slice.len() > 0 && hub_slice[0].0 == dst { hub_slice[0].1 } else { dst }
+                };
+                if batch == 1 {
+                    for (&node, &deg) in dst.iter().zip(cnt.iter()) {
+                        let mut accum = 0.0;
+                        for &s in &trn_slice[..deg as usize] {
+                            // accum += src[s as usize];
+                            unsafe { accum += *src.get_unchecked(s as usize); }
+                        }
+                        trn_slice = &trn_slice[deg as usize..];
+                        session.give((name(node), R::from_slice(&[accum])));
+                    }
+                }
+                else {
+                    let mut accum = vec![0.0; batch];
+                    for (&node, &deg) in dst.iter().zip(cnt.iter()) {
+                        for &s in &trn_slice[..deg as usize] {
+                            let s = s as usize * batch;
+                            for (x, y) in accum.iter_mut().zip(&src[s .. s + batch]) { *x += *y; }
+                        }
+                        trn_slice = &trn_slice[deg as usize..];
+                        session.give((name(node), R::from_slice(&accum)));
+                        for x in &mut accum { *x = 0.0; }
                     }
-                    trn_slice = &trn_slice[deg as usize..];
-                    session.give((dst, accum));
                 }
 
-                // reset src to 0.15 before accumulation
-                for s in &mut src { *s = 0.15; }
+                // reset src to the teleport mass before accumulation
+                ranking.reset();
             }
 
-            // receive data from workers, accumulate in src
+            // receive data from workers, accumulate in src (or totals, for aggregates)
             while let Some((iter, data)) = input2.next() {
                 notificator.notify_at(&iter);
-                for &(node, rank) in data.iter() {
-                    src[node as usize / peers] += rank;
+                for &(node, ref ranks) in data.iter() {
+                    let node = node as usize;
+                    if node < control { ranking.add(layout.slot(node), ranks.as_slice()); }
+                    else              { ranking.receive(node, ranks.as_slice()); }
                 }
             }
         });
 
-        // optionally, do process-local accumulation
-        if use_process_local {
-            let local_base = workers * (index / workers);
-            let local_index = index % workers;
-            let mut acc = vec![0.0; (nodes / workers) + 1];   // holds ranks
-            ranks = ranks.unary_notify(
-                Exchange::new(move |x: &(u32,f32)| (local_base as u64 + (x.0 as u64 % workers as u64))),
-                "aggregation",
+        // combine the partial sums for each hub, and send one sum on to its owner
+        if !hubs.is_empty() {
+            let mut parts = ranks.partition(2, move |x: (Node,R)| (if x.0 as usize >= first { 1 } else { 0 }, x));
+            let hub_parts = parts.pop().unwrap();
+            let hubs = hubs.clone();
+            let mut sums = vec![0.0f32; hubs.len() * batch];    // holds partial sums, by position in hubs
+            let combined = hub_parts.unary_notify(
+                Exchange::new(|x: &(Node,R)| x.0 as u64),
+                "hubs",
                 vec![],
-                move |input, output, iterator| {
+                move |input, output, notificator| {
                     while let Some((iter, data)) = input.next() {
-                        iterator.notify_at(&iter);
-                        for &(node, rank) in data.iter() {
-                            acc[node as usize / workers] += rank;
+                        notificator.notify_at(&iter);
+                        for &(node, ref ranks) in data.iter() {
+                            let h = (node as usize - first) / peers;
+                            for (x, y) in sums[h * batch .. (h + 1) * batch].iter_mut().zip(ranks.as_slice()) { *x += *y; }
                         }
                     }
 
-                    while let Some((item, _)) = iterator.next() {
-                        output.session(&item)
-                              .give_iterator(acc.drain_temp()
-                                                .enumerate()
-                                                .filter(|x| x.1 != 0.0)
-                                                .map(|(u,f)| ((u * workers + local_index) as u32, f)));
-
-                        for _ in 0..(1 + (nodes/workers)) { acc.push(0.0); }
+                    while let Some((iter, _)) = notificator.next() {
+                        let mut session = output.session(&iter);
+                        for (h, ranks) in sums.chunks_mut(batch).enumerate() {
+                            if ranks.iter().any(|&x| x != 0.0) {
+                                session.give((hubs[h], R::from_slice(ranks)));
+                                for x in ranks.iter_mut() { *x = 0.0; }
+                            }
+                        }
                     }
                 }
             );
+            ranks = parts.pop().unwrap().concat(&combined);
+        }
+
+        // optionally, do process-local accumulation
+        if config.use_process_local {
+            ranks = aggregate(&ranks, nodes, workers, index, control, batch);
         }
 
         ranks.connect_loop(cycle);
@@ -158,53 +379,117 @@ pub fn run<G:Graph,A:Allocate>(workers:usize, root:&mut Root<A>, graph:&G, use_p
     // introduce edges into the computation;
     // allow mmaped file to drop
     {
-        for node in 0..graph.nodes() {
-            if node % peers == index {
+        // only this worker's nodes, which is all a shard holds edges for
+        if send_edges {
+            for slot in 0..local {
+                let node = partition.node(index, slot);
                 for dst in graph.edges(node) {
-                    input.send((node as u32, *dst as u32));
+                    input.send((node as Node, *dst));
                 }
             }
         }
     }
     input.close();
     while root.step() { };
+
+    let shard = mem::replace(&mut *result.borrow_mut(), Vec::new());
+    shard
 }
-// returns [src/peers] degrees, (dst, deg) pairs, and a list of [src/peers] endpoints
-fn transpose(mut edges: Vec<Vec<(u32, u32)>>, peers: usize, nodes: usize) -> (Vec<u32>, Vec<(u32, u32)>, Vec<u32>)  {
 
-    let mut deg = vec![0; 1+(nodes as usize / peers)];
+// sums the ranks sent to each node within each process of `workers` workers, on a
+// worker of the process chosen by node, which sends one sum on to the node's owner;
+// identifiers from `control` up carry aggregates, and pass through unchanged
+fn aggregate<G:Scope,R:Ranks>(ranks:&Stream<G,(Node,R)>, nodes:usize, workers:usize, index:usize, control:usize, batch:usize) -> Stream<G,(Node,R)> {
+    let local_base = workers * (index / workers);
+    let local_index = index % workers;
+    let mut acc = vec![0.0; ((nodes / workers) + 1) * batch];   // holds ranks
+    ranks.unary_notify(
+        Exchange::new(move |x: &(Node,R)| local_base as u64 + (x.0 as u64 % workers as u64)),
+        "aggregation",
+        vec![],
+        move |input, output, iterator| {
+            while let Some((iter, data)) = input.next() {
+                iterator.notify_at(&iter);
+                let mut session = output.session(&iter);
+                for (node, ranks) in data.drain_temp() {
+                    if (node as usize) < control {
+                        let u = node as usize / workers * batch;
+                        for (x, y) in acc[u .. u + batch].iter_mut().zip(ranks.as_slice()) { *x += *y; }
+                    }
+                    else { session.give((node, ranks)); }
+                }
+            }
+
+            while let Some((item, _)) = iterator.next() {
+                let mut session = output.session(&item);
+                for (u, ranks) in acc.chunks(batch).enumerate() {
+                    if ranks.iter().any(|&x| x != 0.0) {
+                        session.give(((u * workers + local_index) as Node, R::from_slice(ranks)));
+                    }
+                }
+                for x in &mut acc { *x = 0.0; }
+            }
+        }
+    )
+}
+
+/// Collects the shards returned by `run` on worker 0.
+///
+/// Every worker must call `gather`; worker 0 receives all `(node, rank)` pairs
+/// sorted by node, and the other workers receive an empty vector.
+pub fn gather<A:Allocate>(root:&mut Root<A>, shard:Vec<(Node, f32)>) -> Vec<(Node, f32)> {
+
+    let result = Rc::new(RefCell::new(Vec::new()));
+    let gathered = result.clone();
+
+    let mut input = root.scoped::<u64,_,_>(move |builder| {
+
+        let (input, ranks) = builder.new_input::<(Node, f32)>();
+
+        ranks.unary_stream::<(), _, _>(Exchange::new(|_: &(Node,f32)| 0), "gather", move |input, _output| {
+            while let Some((_time, data)) = input.next() {
+                gathered.borrow_mut().extend(data.drain_temp());
+            }
+        });
+
+        input
+    });
+
+    for pair in shard {
+        input.send(pair);
+    }
+    input.close();
+    while root.step() { };
+
+    let mut ranks = mem::replace(&mut *result.borrow_mut(), Vec::new());
+    ranks.sort_by(|x, y| x.0.cmp(&y.0));
+    ranks
+}
+// returns degrees and a list of endpoints by the slot of their source, and (dst, deg) pairs
+fn transpose(mut edges: Vec<Vec<(Node, Node)>>, partition: &Partition) -> (Vec<u32>, Vec<(Node, u32)>, Vec<Node>)  {
+
+    let mut deg = vec![0; partition.slots()];
     for list in &edges {
         for &(s, _) in list {
-            deg[s as usize / peers] += 1;
+            deg[partition.slot(s as usize)] += 1;
         }
     }
 
-    radix_sort_32(&mut edges, &mut Vec::new(), &|&(_,d)| d);
+    radix_sort_node(&mut edges, &mut Vec::new(), &|&(_,d)| d);
 
-    let mut rev = Vec::<(u32,u32)>::with_capacity(deg.len());
+    let mut rev = Vec::<(Node,u32)>::with_capacity(deg.len());
     let mut trn = Vec::with_capacity(edges.len() * 1024);
-    let mut max = 0;
     for list in edges {
         for (s,d) in list {
             if (rev.len() == 0) || (rev[rev.len()-1].0 < d) {
                 rev.push((d, 0u32));
             }
 
-            if max < s {
-                max = s;
-            }
-            if max < d {
-                max = d;
-            }
-
             let len = rev.len();
             rev[len-1].1 += 1;
-            trn.push(s / peers as u32);
+            trn.push(partition.slot(s as usize) as Node);
         }
     }
-    while deg.len() < (max as usize / peers) {
-        deg.push(0);
-    }
 
     (deg, rev, trn)
 }
@@ -212,38 +497,235 @@ fn transpose(mut edges: Vec<Vec<(u32, u32)>>, peers: usize, nodes: usize) -> (Ve
 #[test]
 fn test_transpose() {
     // three branch star, one worker
-    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(0,2),(0,3))), 1, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(0,2),(0,3))), &Partition::modulo(4, 1));
     assert_eq!(*deg, [3, 0, 0, 0]);
     assert_eq!(*rev, [(1,1),(2,1),(3,1)]);
     assert_eq!(*trn, [0,0,0]);
 
     // three node pipe, one worker
-    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(1,2),(2,3))), 1, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(1,2),(2,3))), &Partition::modulo(4, 1));
     assert_eq!(*deg, [1, 1, 1, 0]);
     assert_eq!(*rev, [(1,1),(2,1),(3,1)]);
     assert_eq!(*trn, [0,1,2]);
 
     // three branch star, first worker of two
-    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(0,2),(0,3))), 2, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(0,2),(0,3))), &Partition::modulo(4, 2));
     assert_eq!(*deg, [3, 0]);
     assert_eq!(*rev, [(1,1),(2,1),(3,1)]);
     assert_eq!(*trn, [0,0,0]);
 
     // three branch star, second worker of two
-    let (deg,rev,trn) = transpose(vec!(vec!()), 2, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!()), &Partition::modulo(4, 2));
     assert_eq!(*deg, [0, 0]);
     assert_eq!(*rev, []);
     assert_eq!(*trn, []);
 
     // three node pipe, first worker of two
-    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(2,3))), 2, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!((0,1),(2,3))), &Partition::modulo(4, 2));
     assert_eq!(*deg, [1, 1]);
     assert_eq!(*rev, [(1,1),(3,1)]);
     assert_eq!(*trn, [0,1]);
 
     // three node pipe, second worker of two
-    let (deg,rev,trn) = transpose(vec!(vec!((1,2))), 2, 4);
+    let (deg,rev,trn) = transpose(vec!(vec!((1,2))), &Partition::modulo(4, 2));
     assert_eq!(*deg, [1, 0]);
     assert_eq!(*rev, [(2,1)]);
     assert_eq!(*trn, [0]);
+
+    // three node pipe, second worker of two ranges
+    let (deg,rev,trn) = transpose(vec!(vec!((2,3))), &Partition::ranges(vec![0, 2, 4]));
+    assert_eq!(*deg, [1, 0]);
+    assert_eq!(*rev, [(3,1)]);
+    assert_eq!(*trn, [0]);
+}
+
+// dataflow tests: computations over a small graph, on several workers
+
+// a computation for `test_ranks` to run
+#[cfg(test)]
+#[derive(Clone)]
+enum Job {
+    Run(Config),
+    Grid(Config),
+    Batch(Config, Vec<Teleport>),   // one computation for each source
+}
+
+// ten nodes: node 5 has no out-edges, node 0 has the most in-edges and node 9 the most out-edges
+#[cfg(test)]
+fn test_graph() -> graphmap::MemoryGraph {
+    graphmap::MemoryGraph(vec![vec![1, 2, 3, 4, 6, 7], vec![0, 2], vec![0], vec![0, 1, 5], vec![5, 0], vec![],
+                               vec![0, 7], vec![6, 2, 3], vec![0], vec![0, 1, 2, 3, 4, 5, 6, 7, 8]])
+}
+
+// runs each of `jobs` over `test_graph` on `workers` workers, and returns the ranks
+// of each computation, in order, as gathered on worker 0
+#[cfg(test)]
+fn test_ranks(workers: usize, jobs: Vec<Job>) -> Vec<Vec<(Node, f32)>> {
+    use std::sync::{Arc, Mutex};
+
+    let ranks = Arc::new(Mutex::new(vec![]));
+    let shared = ranks.clone();
+    timely::execute_from_args(vec!["-w".to_owned(), workers.to_string()].into_iter(), move |root| {
+        let graph = test_graph();
+        for job in jobs.iter() {
+            let shards = match *job {
+                Job::Run(ref config)  => vec![run(root, &graph, config)],
+                Job::Grid(ref config) => vec![grid::run_2d(root, &graph, config)],
+                Job::Batch(ref config, ref sources) => {
+                    let shard = batch::run_batch(root, &graph, config, sources);
+                    (0..sources.len()).map(|k| shard.iter().map(|&(node, ref ranks)| (node, ranks[k])).collect()).collect()
+                },
+            };
+            for shard in shards {
+                let gathered = gather(root, shard);
+                if root.index() == 0 { shared.lock().unwrap().push(gathered); }
+            }
+        }
+    });
+
+    let ranks = ranks.lock().unwrap().clone();
+    ranks
+}
+
+// asserts that `ranks` holds every node of `test_graph`, with the ranks of `expected` to within 1e-5
+#[cfg(test)]
+fn assert_ranks(ranks: &[(Node, f32)], expected: &[(Node, f32)]) {
+    assert_eq!(ranks.len(), 10);
+    assert_eq!(ranks.len(), expected.len());
+    for (x, y) in ranks.iter().zip(expected.iter()) {
+        assert_eq!(x.0, y.0);
+        assert!((x.1 - y.1).abs() < 1e-5, "{:?} differs from {:?}", ranks, expected);
+    }
+}
+
+#[test]
+fn hubs_match_run() {
+    let mut config = Config::new();
+    config.iterations = 10;
+    config.workers = 2;
+    // nodes 0 and 2 have the most in-edges
+    let mut split = config.clone();
+    split.hubs = hubs(&test_graph(), 3);
+    assert_eq!(split.hubs, vec![0, 2]);
+    for &workers in &[4, 6] {
+        let ranks = test_ranks(workers, vec![Job::Run(config.clone()), Job::Run(split.clone())]);
+        assert_ranks(&ranks[1], &ranks[0]);
+    }
+}
+
+// the ranks `run` should give over `test_graph` as `config` says, by power iteration
+// on a single thread in f64, with each iteration as `Ranking` computes it
+#[cfg(test)]
+fn test_reference(config: &Config) -> Vec<(Node, f32)> {
+    let graph = test_graph();
+    let nodes = graph.nodes();
+    let damping = config.damping as f64;
+
+    let teleport = (1.0 - damping) * nodes as f64;
+    let reset: Vec<f64> = match config.teleport {
+        Teleport::Uniform => vec![teleport / nodes as f64; nodes],
+        Teleport::Weights(ref weights) => {
+            let total = weights.iter().fold(0.0, |t, x| t + x.1 as f64);
+            let mut reset = vec![0.0; nodes];
+            for &(node, weight) in weights { reset[node as usize] += teleport * weight as f64 / total; }
+            reset
+        },
+    };
+    let spread: Vec<f64> = match config.dangling {
+        Dangling::Uniform  => vec![1.0 / nodes as f64; nodes],
+        Dangling::Teleport => reset.iter().map(|x| x / teleport).collect(),
+    };
+
+    let mut ranks = reset.clone();
+    if let Some(ref initial) = config.initial {
+        for (x, &y) in ranks.iter_mut().zip(&initial.shard(&Partition::modulo(nodes, 1), 0)) { *x = y as f64; }
+    }
+    let mut last = ranks.clone();
+    let mut residual = 0.0;
+    for iter in 0..config.iterations {
+        if config.tolerance.map_or(false, |tolerance| iter > 1 && residual < tolerance as f64) { break; }
+        residual = ranks.iter().zip(&last).fold(0.0, |t, (x, y)| t + (x - y).abs());
+        last = ranks.clone();
+
+        let mut next = reset.clone();
+        let mut dangling = 0.0;
+        for node in 0..nodes {
+            let edges = graph.edges(node);
+            if edges.is_empty() { dangling += ranks[node]; }
+            for &dst in edges { next[dst as usize] += damping * ranks[node] / edges.len() as f64; }
+        }
+        for (x, y) in next.iter_mut().zip(&spread) { *x += damping * dangling * y; }
+        ranks = next;
+    }
+
+    ranks.into_iter().enumerate().map(|(node, rank)| (node as Node, rank as f32)).collect()
+}
+
+#[test]
+fn run_matches_reference() {
+    let mut configs = vec![];
+    let mut config = Config::new();
+    config.iterations = 12;
+    configs.push(config.clone());
+    config.damping = 0.5;
+    configs.push(config.clone());
+    config.teleport = Teleport::seeds(&[1, 5]);
+    configs.push(config.clone());
+    config.dangling = Dangling::Teleport;
+    configs.push(config.clone());
+    config.teleport = Teleport::Weights(vec![(0, 1.0), (5, 0.5), (9, 2.5)]);
+    configs.push(config.clone());
+    config.partition = Partitioning::Range;
+    configs.push(config.clone());
+    config.partition = Partitioning::Balanced;
+    configs.push(config.clone());
+
+    let jobs: Vec<_> = configs.iter().map(|config| Job::Run(config.clone())).collect();
+    for &workers in &[1, 3] {
+        for (ranks, config) in test_ranks(workers, jobs.clone()).iter().zip(&configs) {
+            assert_ranks(ranks, &test_reference(config));
+        }
+    }
+}
+
+#[test]
+fn tolerance_stops_early() {
+    let mut config = Config::new();
+    config.iterations = 100;
+    let mut tolerant = config.clone();
+    tolerant.tolerance = Some(1e-2);
+
+    let reference = test_reference(&tolerant);
+    assert!(reference.iter().zip(&test_reference(&config)).any(|(x, y)| (x.1 - y.1).abs() > 1e-4));
+    for &workers in &[1, 3] {
+        let ranks = test_ranks(workers, vec![Job::Run(tolerant.clone())]);
+        assert_ranks(&ranks[0], &reference);
+    }
+}
+
+#[test]
+fn initial_and_cached_match_reference() {
+    let directory = tempdir::TempDir::new("initial_and_cached").unwrap();
+    let prefix = directory.path().join("initial").to_string_lossy().into_owned();
+
+    // a file covering the first six nodes only
+    let initial = vec![(0, 4.0), (1, 0.0), (2, 2.0), (3, 1.0), (4, 0.5), (5, 1.5)];
+    output::write(&output::shard_name(&prefix, 0, output::Format::Binary), &initial, output::Format::Binary, None).unwrap();
+    output::merge(&prefix, &Partition::modulo(6, 1), output::Format::Binary).unwrap();
+
+    let mut config = Config::new();
+    config.iterations = 3;
+    config.initial = Some(RankFile::open(&output::merged_name(&prefix, output::Format::Binary)).unwrap());
+    config.cache = Some(Cache { prefix: directory.path().join("cache").to_string_lossy().into_owned(), key: "test".to_owned() });
+
+    // the second run maps the edges the first one saved
+    let reference = test_reference(&config);
+    let ranks = test_ranks(3, vec![Job::Run(config.clone()), Job::Run(config.clone())]);
+    assert_ranks(&ranks[0], &reference);
+    assert_ranks(&ranks[1], &reference);
+
+    // `compute` keys the cache by partition as well
+    let mut cache = config.cache.unwrap();
+    cache.key = format!("{} {:?}", cache.key, Partitioning::Modulo);
+    for index in 0..3 { assert!(cache.load(index, 3).unwrap().is_some()); }
 }
@@ -0,0 +1,141 @@
+use std::mem;
+
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+use std::fs::{ self, File };
+
+use byteorder::{ NativeEndian, ReadBytesExt, WriteBytesExt };
+
+use error::{ Error, Result, InFile };
+use graphmap::Node;
+
+// file format of `<prefix>.meta`, in the byte order of the writing machine:
+//
+//     magic:   [u8; 8],    b"PAGERANK"
+//     version: u32,        VERSION
+//     endian:  u32,        0x01020304, to detect foreign byte orders
+//     id:      u32,        bytes per node identifier in `.targets`
+//     offset:  u32,        bytes per offset in `.offsets`
+//     nodes:   u64,        entries in `.offsets`
+//     edges:   u64,        entries in `.targets` (and `.weights`)
+//     flags:   u32,        WEIGHTED if there is a `.weights` file, SHARDED for a shard
+//
+// followed, for shards only, by
+//
+//     index:   u64,        which shard this is
+//     peers:   u64,        the number of shards
+//     total:   u64,        nodes in the whole graph
+//
+// Shards are written as version 2, and whole graphs as version 1, which builds
+// that know nothing of shards can still read.
+
+const MAGIC: &'static [u8; 8] = b"PAGERANK";
+const VERSION: u32 = 1;
+const VERSION_SHARDED: u32 = 2;
+const ENDIAN: u32 = 0x01020304;
+const WEIGHTED: u32 = 1;
+const SHARDED: u32 = 2;
+
+/// Describes an encoded graph, as recorded in `<prefix>.meta`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Meta {
+    pub nodes: u64,         // number of nodes, the length of `.offsets`
+    pub edges: u64,         // number of edges, the length of `.targets`
+    pub weighted: bool,     // whether `.weights` holds an f32 per edge
+    pub shard: Option<Shard>,   // which part of a larger graph this is, if any
+}
+
+/// Describes one shard of a graph split across `peers` workers.
+///
+/// Shard `index` holds the edges of nodes `index`, `index + peers`, and so on;
+/// entry `i` of its `.offsets` belongs to node `i * peers + index`, and its
+/// `.targets` hold node identifiers of the whole graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub peers: u64,
+    pub total: u64,         // number of nodes in the whole graph
+}
+
+impl Meta {
+
+    /// Writes `<prefix>.meta`.
+    pub fn write(&self, prefix: &str) -> Result<()> {
+        let filename = format!("{}.meta", prefix);
+        self.write_to(&mut BufWriter::new(File::create(&filename).in_file(&filename)?)).in_file(&filename)
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<NativeEndian>(if self.shard.is_some() { VERSION_SHARDED } else { VERSION })?;
+        writer.write_u32::<NativeEndian>(ENDIAN)?;
+        writer.write_u32::<NativeEndian>(mem::size_of::<Node>() as u32)?;
+        writer.write_u32::<NativeEndian>(mem::size_of::<u64>() as u32)?;
+        writer.write_u64::<NativeEndian>(self.nodes)?;
+        writer.write_u64::<NativeEndian>(self.edges)?;
+        let flags = (if self.weighted { WEIGHTED } else { 0 }) | (if self.shard.is_some() { SHARDED } else { 0 });
+        writer.write_u32::<NativeEndian>(flags)?;
+        if let Some(shard) = self.shard {
+            writer.write_u64::<NativeEndian>(shard.index)?;
+            writer.write_u64::<NativeEndian>(shard.peers)?;
+            writer.write_u64::<NativeEndian>(shard.total)?;
+        }
+        writer.flush()
+    }
+
+    /// Reads `<prefix>.meta`, rejecting files this build cannot interpret.
+    pub fn read(prefix: &str) -> Result<Meta> {
+        let filename = format!("{}.meta", prefix);
+        let invalid = |message: &str| Error::Format(filename.clone(), message.to_owned());
+
+        let mut reader = BufReader::new(File::open(&filename).in_file(&filename)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).in_file(&filename)?;
+        if &magic != MAGIC { return Err(invalid("not a graph description")); }
+
+        let version = reader.read_u32::<NativeEndian>().in_file(&filename)?;
+        let endian = reader.read_u32::<NativeEndian>().in_file(&filename)?;
+        if endian == ENDIAN.swap_bytes() { return Err(invalid("written with a foreign byte order")); }
+        if endian != ENDIAN { return Err(invalid("corrupt byte order marker")); }
+        if version != VERSION && version != VERSION_SHARDED { return Err(invalid(&format!("unsupported version {}", version))); }
+
+        let id = reader.read_u32::<NativeEndian>().in_file(&filename)?;
+        let offset = reader.read_u32::<NativeEndian>().in_file(&filename)?;
+        if id as usize != mem::size_of::<Node>() {
+            return Err(invalid(&format!("{}-byte node identifiers, but built for {}-byte (see the ids64 feature)", id, mem::size_of::<Node>())));
+        }
+        if offset as usize != mem::size_of::<u64>() { return Err(invalid(&format!("{}-byte offsets unsupported", offset))); }
+
+        let nodes = reader.read_u64::<NativeEndian>().in_file(&filename)?;
+        let edges = reader.read_u64::<NativeEndian>().in_file(&filename)?;
+        let flags = reader.read_u32::<NativeEndian>().in_file(&filename)?;
+
+        let shard = if flags & SHARDED != 0 {
+            let index = reader.read_u64::<NativeEndian>().in_file(&filename)?;
+            let peers = reader.read_u64::<NativeEndian>().in_file(&filename)?;
+            let total = reader.read_u64::<NativeEndian>().in_file(&filename)?;
+            if index >= peers { return Err(invalid(&format!("shard {} of only {}", index, peers))); }
+            Some(Shard { index: index, peers: peers, total: total })
+        }
+        else { None };
+
+        Ok(Meta { nodes: nodes, edges: edges, weighted: flags & WEIGHTED != 0, shard: shard })
+    }
+
+    /// Checks that the files under `prefix` have the sizes this description implies.
+    pub fn validate(&self, prefix: &str) -> Result<()> {
+        check_size(&format!("{}.offsets", prefix), self.nodes * mem::size_of::<u64>() as u64)?;
+        check_size(&format!("{}.targets", prefix), self.edges * mem::size_of::<Node>() as u64)?;
+        if self.weighted {
+            check_size(&format!("{}.weights", prefix), self.edges * mem::size_of::<f32>() as u64)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_size(filename: &str, expected: u64) -> Result<()> {
+    let size = fs::metadata(filename).in_file(filename)?.len();
+    if size != expected {
+        Err(Error::Format(filename.to_owned(), format!("expected {} bytes, found {}", expected, size)))
+    }
+    else { Ok(()) }
+}
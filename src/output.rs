@@ -0,0 +1,165 @@
+use std::fmt;
+use std::mem;
+
+use std::io::{ BufRead, BufReader, BufWriter, Read, Write };
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use error::{ Error, Result, InFile };
+use graphmap::Node;
+use partition::Partition;
+use typedrw::TypedMemoryMap;
+
+/// How ranks are written to disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Binary,     // f32 per node, in order of node; mmappable as `TypedMemoryMap<f32>`
+    Text,       // `node\trank` (or `label\trank`) lines, in order of node
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match *self {
+            Format::Binary => "ranks",
+            Format::Text   => "tsv",
+        }
+    }
+}
+
+/// The file holding worker `index`'s shard, `<prefix>-<index>.ranks` or `.tsv`.
+pub fn shard_name(prefix: &str, index: usize, format: Format) -> String {
+    format!("{}-{}.{}", prefix, index, format.extension())
+}
+
+/// The file holding all ranks, `<prefix>.ranks` or `.tsv`.
+pub fn merged_name(prefix: &str, format: Format) -> String {
+    format!("{}.{}", prefix, format.extension())
+}
+
+/// Writes a shard, as returned by `run`, to `filename`.
+///
+/// Entry `i` of worker `index`'s shard is the rank of the node in slot `i` of the
+/// partition `run` used, which is node `i * peers + index` by default. Binary
+/// shards hold only the ranks; text shards lead each rank by its node or, given
+/// `labels`, by the node's label.
+pub fn write(filename: &str, shard: &[(Node, f32)], format: Format, labels: Option<&[String]>) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(filename).in_file(filename)?);
+    for &(node, rank) in shard {
+        match (format, labels) {
+            (Format::Binary, _)           => { writer.write_all(&unsafe { mem::transmute::<_, [u8; 4]>(rank) }).in_file(filename)?; },
+            (Format::Text, None)          => { writeln!(writer, "{}\t{}", node, rank).in_file(filename)?; },
+            (Format::Text, Some(labels))  => { writeln!(writer, "{}\t{}", labels[node as usize], rank).in_file(filename)?; },
+        }
+    }
+    writer.flush().in_file(filename)
+}
+
+/// Merges the shards under `prefix`, written by the workers of `partition`, into
+/// the single file `merged_name(prefix, format)`.
+pub fn merge(prefix: &str, partition: &Partition, format: Format) -> Result<()> {
+    let merged = merged_name(prefix, format);
+    let mut writer = BufWriter::new(File::create(&merged).in_file(&merged)?);
+    let names: Vec<_> = (0..partition.peers()).map(|index| shard_name(prefix, index, format)).collect();
+    // each shard holds its nodes in order, so node i is the next entry of its owner's shard
+    match format {
+        Format::Binary => {
+            let mut readers = Vec::new();
+            for name in &names {
+                readers.push(BufReader::new(File::open(name).in_file(name)?));
+            }
+            let mut bytes = [0u8; 4];
+            for node in 0..partition.nodes() {
+                let owner = partition.owner(node);
+                readers[owner].read_exact(&mut bytes).in_file(&names[owner])?;
+                writer.write_all(&bytes).in_file(&merged)?;
+            }
+        },
+        Format::Text => {
+            let mut readers = Vec::new();
+            for name in &names {
+                readers.push(BufReader::new(File::open(name).in_file(name)?).lines());
+            }
+            for node in 0..partition.nodes() {
+                let owner = partition.owner(node);
+                match readers[owner].next() {
+                    Some(line) => { writeln!(writer, "{}", line.in_file(&names[owner])?).in_file(&merged)?; },
+                    None => return Err(Error::Format(names[owner].clone(), format!("no line for node {}", node))),
+                }
+            }
+        },
+    }
+    writer.flush().in_file(&merged)
+}
+
+/// A merged binary rank file, mapped once and shared by the workers of a process.
+#[derive(Clone)]
+pub struct RankFile {
+    filename: String,
+    ranks: Arc<TypedMemoryMap<f32>>,
+}
+
+impl RankFile {
+    /// Maps `filename`, which must be a merged binary rank file, as written by
+    /// `merge` to `<prefix>.ranks`, holding a finite, non-negative rank per node.
+    pub fn open(filename: &str) -> Result<RankFile> {
+        // text files can have a size that is a multiple of four, and hold bytes that read as valid ranks
+        if Path::new(filename).extension().map_or(true, |x| x != Format::Binary.extension()) {
+            return Err(Error::Format(filename.to_owned(), format!("not a binary rank file, named <prefix>.{}", Format::Binary.extension())));
+        }
+        let ranks = TypedMemoryMap::<f32>::new(filename.to_owned())?;
+        if let Some(node) = ranks[..].iter().position(|&rank| !(rank.is_finite() && rank >= 0.0)) {
+            return Err(Error::Format(filename.to_owned(), format!("rank of node {} is not finite and non-negative: {}", node, ranks[..][node])));
+        }
+        Ok(RankFile { filename: filename.to_owned(), ranks: Arc::new(ranks) })
+    }
+
+    /// Worker `index`'s shard of the ranks.
+    ///
+    /// Entry `i` of the result is the rank of the node in slot `i` of worker `index`
+    /// under `partition`; the result stops at the end of the file, which may hold
+    /// fewer nodes than the graph.
+    pub fn shard(&self, partition: &Partition, index: usize) -> Vec<f32> {
+        let ranks = &self.ranks[..];
+        // a worker's nodes increase with their slots
+        (0..partition.local(index)).map(|slot| partition.node(index, slot))
+                                   .take_while(|&node| node < ranks.len())
+                                   .map(|node| ranks[node])
+                                   .collect()
+    }
+}
+
+impl fmt::Debug for RankFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RankFile({:?})", self.filename)
+    }
+}
+
+#[test]
+fn write_and_merge() {
+    let target = ::tempdir::TempDir::new("write_and_merge").unwrap();
+    let prefix = target.path().join("ranks").to_string_lossy().into_owned();
+
+    // five nodes over two workers
+    let shards = vec![vec![(0, 0.5), (2, 1.5), (4, 2.5)], vec![(1, 1.0), (3, 2.0)]];
+    for &format in &[Format::Binary, Format::Text] {
+        for (index, shard) in shards.iter().enumerate() {
+            write(&shard_name(&prefix, index, format), shard, format, None).unwrap();
+        }
+        merge(&prefix, &Partition::modulo(5, 2), format).unwrap();
+    }
+
+    let ranks = TypedMemoryMap::<f32>::new(merged_name(&prefix, Format::Binary)).unwrap();
+    assert_eq!(&ranks[..], &[0.5, 1.0, 1.5, 2.0, 2.5]);
+    let file = RankFile::open(&merged_name(&prefix, Format::Binary)).unwrap();
+    assert_eq!(file.shard(&Partition::modulo(5, 3), 1), vec![1.0, 2.5]);
+    assert_eq!(file.shard(&Partition::ranges(vec![0, 2, 5]), 1), vec![1.5, 2.0, 2.5]);
+    assert!(RankFile::open(&merged_name(&prefix, Format::Text)).is_err());
+    let negative = target.path().join("negative").to_string_lossy().into_owned();
+    write(&merged_name(&negative, Format::Binary), &[(0, 1.0), (1, -1.0)], Format::Binary, None).unwrap();
+    assert!(RankFile::open(&merged_name(&negative, Format::Binary)).is_err());
+
+    let mut text = String::new();
+    File::open(merged_name(&prefix, Format::Text)).unwrap().read_to_string(&mut text).unwrap();
+    assert_eq!(text, "0\t0.5\n1\t1\n2\t1.5\n3\t2\n4\t2.5\n");
+}
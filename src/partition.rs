@@ -0,0 +1,148 @@
+use graphmap::Graph;
+
+/// How nodes are assigned to workers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partitioning {
+    Modulo,     // node `n` to worker `n % peers`
+    Range,      // contiguous ranges of equally many nodes
+    Balanced,   // contiguous ranges of roughly equally many edges
+}
+
+impl Partitioning {
+    pub fn from_name(name: &str) -> Option<Partitioning> {
+        match name {
+            "modulo"   => Some(Partitioning::Modulo),
+            "range"    => Some(Partitioning::Range),
+            "balanced" => Some(Partitioning::Balanced),
+            _          => None,
+        }
+    }
+}
+
+/// An assignment of each of `nodes` nodes to one of `peers` workers.
+///
+/// Each worker numbers the nodes it owns from zero, in increasing order of node;
+/// this number is the node's slot, and indexes the worker's per-node state.
+#[derive(Clone, Debug)]
+pub struct Partition {
+    nodes: usize,
+    peers: usize,
+    bounds: Option<Vec<usize>>,     // first node of each worker, then `nodes`; none for modulo
+}
+
+impl Partition {
+
+    /// Node `n` to worker `n % peers`, the layout of shards and rank files.
+    pub fn modulo(nodes: usize, peers: usize) -> Partition {
+        Partition { nodes: nodes, peers: peers, bounds: None }
+    }
+
+    /// Nodes `bounds[w] .. bounds[w + 1]` to worker `w`.
+    pub fn ranges(bounds: Vec<usize>) -> Partition {
+        assert!(bounds.len() > 1 && bounds[0] == 0, "ranges must start from node 0");
+        assert!(bounds.windows(2).all(|x| x[0] <= x[1]), "ranges must not decrease");
+        Partition { nodes: bounds[bounds.len() - 1], peers: bounds.len() - 1, bounds: Some(bounds) }
+    }
+
+    /// Partitions the nodes of `graph` over `peers` workers as `partitioning` says.
+    ///
+    /// `Balanced` weighs each node by its out-edges plus one, as a worker's
+    /// time per iteration goes mostly to the out-edges of the nodes it owns.
+    pub fn new<G:Graph>(partitioning: Partitioning, graph: &G, peers: usize) -> Partition {
+        let nodes = graph.nodes();
+        match partitioning {
+            Partitioning::Modulo => Partition::modulo(nodes, peers),
+            Partitioning::Range => Partition::ranges((0..peers + 1).map(|w| w * nodes / peers).collect()),
+            Partitioning::Balanced => {
+                let total = (0..nodes).fold(0u64, |t, node| t + graph.edges(node).len() as u64 + 1);
+                let mut bounds = vec![0];
+                let mut sum = 0u64;
+                for node in 0..nodes {
+                    // start worker k at the first node with k / peers of the weight before it
+                    while bounds.len() < peers && sum * peers as u64 >= total * bounds.len() as u64 {
+                        bounds.push(node);
+                    }
+                    sum += graph.edges(node).len() as u64 + 1;
+                }
+                while bounds.len() <= peers { bounds.push(nodes); }
+                Partition::ranges(bounds)
+            },
+        }
+    }
+
+    pub fn nodes(&self) -> usize { self.nodes }
+    pub fn peers(&self) -> usize { self.peers }
+
+    /// The worker owning `node`.
+    pub fn owner(&self, node: usize) -> usize {
+        match self.bounds {
+            None => node % self.peers,
+            Some(ref bounds) => {
+                // the first worker whose range ends after node
+                let (mut lower, mut upper) = (0, self.peers);
+                while lower < upper {
+                    let middle = (lower + upper) / 2;
+                    if bounds[middle + 1] <= node { lower = middle + 1; }
+                    else                          { upper = middle; }
+                }
+                lower
+            },
+        }
+    }
+
+    /// The slot of `node` at its owner.
+    pub fn slot(&self, node: usize) -> usize {
+        match self.bounds {
+            None => node / self.peers,
+            Some(ref bounds) => node - bounds[self.owner(node)],
+        }
+    }
+
+    /// The node in slot `slot` of worker `index`.
+    pub fn node(&self, index: usize, slot: usize) -> usize {
+        match self.bounds {
+            None => slot * self.peers + index,
+            Some(ref bounds) => bounds[index] + slot,
+        }
+    }
+
+    /// The number of nodes worker `index` owns.
+    pub fn local(&self, index: usize) -> usize {
+        match self.bounds {
+            None => if index < self.nodes { (self.nodes - index - 1) / self.peers + 1 } else { 0 },
+            Some(ref bounds) => bounds[index + 1] - bounds[index],
+        }
+    }
+
+    /// The number of nodes the busiest worker owns, to which per-node state is sized.
+    pub fn slots(&self) -> usize {
+        match self.bounds {
+            None => (self.nodes + self.peers - 1) / self.peers,
+            Some(ref bounds) => bounds.windows(2).map(|x| x[1] - x[0]).max().unwrap_or(0),
+        }
+    }
+}
+
+#[test]
+fn partition_nodes() {
+    use graphmap::MemoryGraph;
+
+    // a hub of nine edges, weighing as much as its five leaves together
+    let graph = MemoryGraph(vec![vec![1, 2, 3, 4, 5, 1, 2, 3, 4], vec![0], vec![0], vec![0], vec![0], vec![0]]);
+    for &(partitioning, ref local) in &[(Partitioning::Modulo, vec![3, 3]),
+                                         (Partitioning::Range, vec![3, 3]),
+                                         (Partitioning::Balanced, vec![1, 5])] {
+        let partition = Partition::new(partitioning, &graph, 2);
+        assert_eq!((0..2).map(|w| partition.local(w)).collect::<Vec<_>>(), *local);
+        assert_eq!(partition.slots(), *local.iter().max().unwrap());
+        for node in 0..6 {
+            let (owner, slot) = (partition.owner(node), partition.slot(node));
+            assert!(slot < partition.local(owner));
+            assert_eq!(partition.node(owner, slot), node);
+        }
+    }
+
+    // empty ranges own nothing
+    let partition = Partition::ranges(vec![0, 0, 2, 2, 3]);
+    assert_eq!((0..3).map(|node| partition.owner(node)).collect::<Vec<_>>(), vec![1, 1, 3]);
+}
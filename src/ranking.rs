@@ -0,0 +1,239 @@
+use std::slice;
+
+use timely::Data;
+
+use graphmap::Node;
+use teleport::Teleport;
+use partition::Partition;
+use {Config, Dangling};
+
+// Per-iteration aggregates share the rank channel with the ranks themselves.
+// The value of aggregate `k` that worker `v` sends to worker `w` travels with
+// identifier `control + (k * peers + v) * peers + w`, where `control` is the
+// first multiple of `peers` above every node, so that the exchange by identifier
+// routes it to `w`. Each worker keeps the values apart by sender and sums them
+// in order of sender, so that all workers arrive at the same totals, and stop
+// at the same iteration.
+pub const RESIDUAL: usize = 0;      // L1 change in ranks over the previous iteration
+pub const DANGLING: usize = 1;      // rank held by nodes without out-edges
+pub const AGGREGATES: usize = 2;
+
+/// The ranks a message carries for one node: one for each computation of a batch.
+pub trait Ranks: Data {
+    fn from_slice(ranks: &[f32]) -> Self;
+    fn as_slice(&self) -> &[f32];
+}
+
+impl Ranks for f32 {
+    fn from_slice(ranks: &[f32]) -> f32 { ranks[0] }
+    fn as_slice(&self) -> &[f32] { slice::from_ref(self) }
+}
+
+impl Ranks for Vec<f32> {
+    fn from_slice(ranks: &[f32]) -> Vec<f32> { ranks.to_vec() }
+    fn as_slice(&self) -> &[f32] { &self[..] }
+}
+
+/// A worker's ranks of the nodes it owns, for each of a batch of computations,
+/// with what it needs to take them from one iteration to the next.
+///
+/// Every PageRank dataflow starts each iteration with `start`, sends the records
+/// of `aggregates` along with its ranks, and hands what it receives to `add` and
+/// `receive`; how ranks travel along edges is up to the dataflow.
+pub struct Ranking {
+    pub src: Vec<f32>,      // ranks of slot `s` at `src[s * batch .. (s + 1) * batch]`
+    pub batch: usize,       // number of computations
+    index: usize,
+    peers: usize,
+    nodes: usize,
+    local: usize,           // nodes owned here
+    control: usize,         // first identifier of aggregates
+    iterations: usize,
+    tolerance: Option<f32>,
+    damping: f32,
+    dangling: Dangling,
+    reset: Vec<f32>,        // teleport masses, which each iteration starts from
+    reset_total: f32,       // teleport mass of all nodes, for each computation
+    last: Vec<f32>,         // ranks of the previous iteration
+    initial: Option<Vec<f32>>,
+    parts: Vec<f32>,        // aggregates received, `batch` for each kind and sender
+}
+
+impl Ranking {
+    /// The ranks of worker `index` under `partition`, for one computation with
+    /// each of `sources` as its teleport distribution, as `config` says.
+    ///
+    /// Rank enters the graph only by teleportation, so `config.damping` must be
+    /// below 1.
+    pub fn new(config: &Config, partition: &Partition, index: usize, sources: &[Teleport]) -> Ranking {
+        assert!(config.damping >= 0.0 && config.damping < 1.0, "damping must lie in [0, 1)");
+        let nodes = partition.nodes();
+        let peers = partition.peers();
+        let batch = sources.len();
+        let teleport = 1.0 - config.damping;   // average rank each node receives from teleportation
+
+        let mut reset = vec![0.0; batch * partition.slots()];
+        for (k, source) in sources.iter().enumerate() {
+            for (s, mass) in source.shard(teleport * nodes as f32, partition, index).into_iter().enumerate() {
+                reset[s * batch + k] = mass;
+            }
+        }
+
+        let control = peers * (nodes / peers + 1);
+        assert!(control + AGGREGATES * peers * peers <= Node::max_value() as usize, "too many nodes for their identifiers");
+
+        Ranking {
+            src: vec![],
+            batch: batch,
+            index: index,
+            peers: peers,
+            nodes: nodes,
+            local: partition.local(index),
+            control: control,
+            iterations: config.iterations,
+            tolerance: config.tolerance,
+            damping: config.damping,
+            dangling: config.dangling,
+            reset: reset,
+            reset_total: teleport * nodes as f32,
+            last: vec![],
+            initial: config.initial.as_ref().map(|x| x.shard(partition, index)),
+            parts: vec![0.0; AGGREGATES * peers * batch],
+        }
+    }
+
+    /// The first identifier beyond every node; larger ones carry aggregates.
+    pub fn control(&self) -> usize { self.control }
+
+    /// Adds `ranks` to those of slot `slot`.
+    pub fn add(&mut self, slot: usize, ranks: &[f32]) {
+        for (x, y) in self.src[slot * self.batch .. (slot + 1) * self.batch].iter_mut().zip(ranks) { *x += *y; }
+    }
+
+    /// Adds the aggregate values that arrived with identifier `id`.
+    pub fn receive(&mut self, id: usize, values: &[f32]) {
+        let part = (id - self.control) / self.peers;  // kind * peers + sender
+        for (x, y) in self.parts[part * self.batch .. (part + 1) * self.batch].iter_mut().zip(values) { *x += *y; }
+    }
+
+    // takes the totals of aggregate `kind`, summed in order of sender
+    fn total(&mut self, kind: usize) -> Vec<f64> {
+        let batch = self.batch;
+        let mut total = vec![0.0f64; batch];
+        for part in self.parts[kind * self.peers * batch .. (kind + 1) * self.peers * batch].chunks_mut(batch) {
+            for (x, y) in total.iter_mut().zip(part.iter_mut()) {
+                *x += *y as f64;
+                *y = 0.0;
+            }
+        }
+        total
+    }
+
+    /// Begins iteration `iter`, and returns whether the ranks are final.
+    ///
+    /// Ranks start from the teleport mass, or from `config.initial`, and each
+    /// later iteration receives the rank of the previous one's dangling nodes.
+    /// With a tolerance, ranks are final once the L1 change of every computation
+    /// has fallen below it.
+    pub fn start(&mut self, iter: usize) -> bool {
+        let batch = self.batch;
+
+        if iter == 0 {
+            self.src = self.reset.clone();
+            if let Some(initial) = self.initial.take() {
+                for (s, &rank) in initial.iter().take(self.local).enumerate() {
+                    for x in &mut self.src[s * batch .. (s + 1) * batch] { *x = rank; }
+                }
+            }
+        }
+
+        // hand out the rank of last iteration's dangling nodes
+        for (k, mass) in self.total(DANGLING).into_iter().enumerate() {
+            let mass = self.damping * mass as f32;
+            if mass > 0.0 {
+                match self.dangling {
+                    Dangling::Uniform => {
+                        let share = mass / self.nodes as f32;
+                        for s in 0..self.local { self.src[s * batch + k] += share; }
+                    },
+                    Dangling::Teleport => {
+                        let scale = mass / self.reset_total;
+                        for s in 0..self.local { self.src[s * batch + k] += scale * self.reset[s * batch + k]; }
+                    },
+                }
+            }
+        }
+
+        // the residual of the previous iteration is complete; check for convergence
+        let residuals = self.total(RESIDUAL);
+        let converged = match self.tolerance {
+            Some(tolerance) => iter > 1 && residuals.iter().all(|&r| r < tolerance as f64),
+            None => false,
+        };
+
+        if self.tolerance.is_some() && iter > 1 && self.index == 0 {
+            let residual = residuals.iter().cloned().fold(0.0, f64::max);
+            println!("residual after iteration {}: {}", iter - 1, residual);
+        }
+
+        iter == self.iterations || converged
+    }
+
+    /// This worker's contributions to the aggregates of iteration `iter`, as
+    /// `(identifier, values)` records for every worker, where `dangling(s)` says
+    /// whether slot `s` has no out-edges.
+    pub fn aggregates<F: Fn(usize)->bool>(&mut self, iter: usize, dangling: F) -> Vec<(Node, Vec<f32>)> {
+        let batch = self.batch;
+        let mut values = vec![];
+
+        // measure how far ranks moved, to be summed across workers
+        if self.tolerance.is_some() {
+            if iter > 0 {
+                let mut residual = vec![0.0f64; batch];
+                for (i, (x, y)) in self.src[.. self.local * batch].iter().zip(&self.last).enumerate() {
+                    residual[i % batch] += (x - y).abs() as f64;
+                }
+                values.push((RESIDUAL, residual));
+            }
+            self.last.clone_from(&self.src);
+        }
+
+        // collect the rank of dangling nodes, to be handed out next iteration
+        let mut mass = vec![0.0f64; batch];
+        for s in 0..self.local {
+            if dangling(s) {
+                for k in 0..batch { mass[k] += self.src[s * batch + k] as f64; }
+            }
+        }
+        values.push((DANGLING, mass));
+
+        let mut records = vec![];
+        for (kind, value) in values {
+            let value: Vec<f32> = value.into_iter().map(|x| x as f32).collect();
+            for worker in 0..self.peers {
+                records.push(((self.control + (kind * self.peers + self.index) * self.peers + worker) as Node, value.clone()));
+            }
+        }
+        records
+    }
+
+    /// Sets the ranks back to the teleport mass, to accumulate the next iteration.
+    pub fn reset(&mut self) {
+        self.src.clone_from(&self.reset);
+    }
+
+    /// This worker's shard of the ranks, as `(node, ranks)` pairs in order of node.
+    pub fn shard<R: Ranks>(&self, partition: &Partition) -> Vec<(Node, R)> {
+        (0..self.local).map(|s| (partition.node(self.index, s) as Node, R::from_slice(&self.src[s * self.batch .. (s + 1) * self.batch])))
+                       .collect()
+    }
+}
+
+#[test]
+#[should_panic(expected = "damping must lie in [0, 1)")]
+fn damping_one_rejected() {
+    // with no teleport mass, every rank would stay zero
+    let mut config = Config::new();
+    config.damping = 1.0;
+    Ranking::new(&config, &Partition::modulo(4, 1), 0, &[Teleport::Uniform]);
+}
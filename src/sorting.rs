@@ -1,6 +1,8 @@
 use std::mem;
 use timely::drain::DrainExt;
 
+use graphmap::Node;
+
 #[derive(Debug)]
 pub struct SegmentList<T> {
     size:     usize,
@@ -40,6 +42,22 @@ pub fn radix_sort_32<V: Copy+Default, F: Fn(&V)->u32>(data: &mut Vec<Vec<V>>, fr
     radix_shuf(data, free, &|x| ((func(x) >> 24) & 0xFF) as u8);
 }
 
+pub fn radix_sort_64<V: Copy+Default, F: Fn(&V)->u64>(data: &mut Vec<Vec<V>>, free: &mut Vec<Vec<V>>, func: &F) {
+    radix_sort_32(data, free, &|x| func(x) as u32);
+    radix_sort_32(data, free, &|x| (func(x) >> 32) as u32);
+}
+
+// sorts by node identifier, with one pass per byte of identifier
+#[cfg(not(feature = "ids64"))]
+pub fn radix_sort_node<V: Copy+Default, F: Fn(&V)->Node>(data: &mut Vec<Vec<V>>, free: &mut Vec<Vec<V>>, func: &F) {
+    radix_sort_32(data, free, func);
+}
+
+#[cfg(feature = "ids64")]
+pub fn radix_sort_node<V: Copy+Default, F: Fn(&V)->Node>(data: &mut Vec<Vec<V>>, free: &mut Vec<Vec<V>>, func: &F) {
+    radix_sort_64(data, free, func);
+}
+
 pub fn radix_shuf<V: Copy+Default, F: Fn(&V)->u8>(data: &mut Vec<Vec<V>>, free: &mut Vec<Vec<V>>, func: &F) {
 
     let mut part = vec![]; for _ in 0..256 { part.push(free.pop().unwrap_or(Vec::with_capacity(1024))); }
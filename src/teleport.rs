@@ -0,0 +1,110 @@
+use std::io::{ BufRead, BufReader };
+use std::fs::File;
+
+use error::{ Error, Result, InFile };
+use graphmap::Node;
+use partition::Partition;
+
+/// Where a random surfer lands when it teleports rather than following an edge.
+#[derive(Clone, Debug)]
+pub enum Teleport {
+    Uniform,                    // every node equally
+    Weights(Vec<(Node, f32)>),  // listed nodes, in proportion to their weights
+}
+
+impl Teleport {
+
+    /// Teleports only to `nodes`, each equally.
+    pub fn seeds(nodes: &[Node]) -> Teleport {
+        Teleport::Weights(nodes.iter().map(|&node| (node, 1.0)).collect())
+    }
+
+    /// Reads a teleport vector from a text file.
+    ///
+    /// Each line holds a node identifier, optionally followed by a weight (which
+    /// defaults to 1.0), separated by whitespace. Lines starting with `#` are skipped.
+    /// Weights must be finite and not negative.
+    pub fn load(filename: &str) -> Result<Teleport> {
+        let file = BufReader::new(File::open(filename).in_file(filename)?);
+        let mut weights = Vec::new();
+        for (number, line) in file.lines().enumerate() {
+            let line = line.in_file(filename)?;
+            if line.starts_with('#') || line.trim().len() == 0 { continue; }
+            let mut elts = line.split_whitespace();
+            let node = elts.next().and_then(|x| x.parse::<Node>().ok());
+            let weight = elts.next().map(|x| x.parse::<f32>().ok()).unwrap_or(Some(1.0));
+            match (node, weight) {
+                (Some(node), Some(weight)) if weight >= 0.0 && weight.is_finite() => weights.push((node, weight)),
+                (Some(_), Some(weight)) => return Err(Error::Format(filename.to_owned(), format!("line {}: weight must be finite and not negative: {}", number + 1, weight))),
+                _ => return Err(Error::Format(filename.to_owned(), format!("line {}: malformed teleport weight", number + 1))),
+            }
+        }
+        Ok(Teleport::Weights(weights))
+    }
+
+    /// The total weight of the nodes below `nodes`, which `shard` needs to be positive.
+    pub fn total(&self, nodes: usize) -> f64 {
+        match *self {
+            Teleport::Uniform => nodes as f64,
+            Teleport::Weights(ref weights) => weights.iter().filter(|x| (x.0 as usize) < nodes).fold(0.0f64, |t, x| t + x.1 as f64),
+        }
+    }
+
+    /// Splits `mass` of teleport rank over the nodes `partition` gives worker `index`.
+    ///
+    /// Returns one entry for each of the `partition.slots()` local slots.
+    pub fn shard(&self, mass: f32, partition: &Partition, index: usize) -> Vec<f32> {
+        let nodes = partition.nodes();
+        let mut reset = vec![0.0; partition.slots()];
+        match *self {
+            Teleport::Uniform => {
+                let share = mass / nodes as f32;
+                for s in 0..partition.local(index) { reset[s] = share; }
+            },
+            Teleport::Weights(ref weights) => {
+                let total = self.total(nodes);
+                assert!(total > 0.0, "teleport weights must not all be zero");
+                for &(node, weight) in weights {
+                    let node = node as usize;
+                    if node < nodes && partition.owner(node) == index {
+                        reset[partition.slot(node)] += (mass as f64 * weight as f64 / total) as f32;
+                    }
+                }
+            },
+        }
+        reset
+    }
+}
+
+#[test]
+fn teleport_shard() {
+    // uniform over five nodes, second worker of two
+    assert_eq!(Teleport::Uniform.shard(5.0, &Partition::modulo(5, 2), 1), vec![1.0, 1.0, 0.0]);
+
+    // seeds, both workers of two
+    let seeds = Teleport::seeds(&[1, 2, 3, 9]);
+    assert_eq!(seeds.shard(3.0, &Partition::modulo(4, 2), 0), vec![0.0, 1.0]);
+    assert_eq!(seeds.shard(3.0, &Partition::modulo(4, 2), 1), vec![1.0, 1.0]);
+
+    // weights, one worker
+    let weights = Teleport::Weights(vec![(0, 1.0), (2, 3.0)]);
+    assert_eq!(weights.shard(4.0, &Partition::modulo(3, 1), 0), vec![1.0, 0.0, 3.0]);
+}
+
+#[test]
+fn teleport_load() {
+    use std::io::Write;
+
+    let directory = ::tempdir::TempDir::new("teleport_load").unwrap();
+    let filename = directory.path().join("teleport.txt").to_string_lossy().into_owned();
+
+    File::create(&filename).unwrap().write_all(b"# node weight\n0 2.5\n\n3\n").unwrap();
+    let teleport = Teleport::load(&filename).unwrap();
+    assert_eq!(teleport.total(4), 3.5);
+    assert_eq!(teleport.total(3), 2.5);
+
+    for line in &["1 -1\n", "1 inf\n", "1 NaN\n"] {
+        File::create(&filename).unwrap().write_all(line.as_bytes()).unwrap();
+        assert!(Teleport::load(&filename).is_err(), "accepted {:?}", line);
+    }
+}
@@ -0,0 +1,410 @@
+use std::io::{ BufRead, BufReader, BufWriter, Lines, Read, Seek, SeekFrom, Write };
+use std::fs::{ self, File };
+use std::result;
+use std::thread;
+
+use getopts;
+
+use error::{ Error, Result, InFile };
+use encode::{ read_edge, write_edge };
+use labels::Dictionary;
+use graphmap::Node;
+
+/// How the columns of an edge list line are separated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delimiter {
+    Tab,
+    Space,
+    Comma,
+    Whitespace, // any run of spaces and tabs
+}
+
+impl Delimiter {
+    /// Recognizes the names `tab`, `space`, `comma` and `whitespace`.
+    pub fn from_name(name: &str) -> Option<Delimiter> {
+        match name {
+            "tab"        => Some(Delimiter::Tab),
+            "space"      => Some(Delimiter::Space),
+            "comma"      => Some(Delimiter::Comma),
+            "whitespace" => Some(Delimiter::Whitespace),
+            _            => None,
+        }
+    }
+
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match *self {
+            Delimiter::Tab        => line.split('\t').collect(),
+            Delimiter::Space      => line.split(' ').collect(),
+            Delimiter::Comma      => line.split(',').collect(),
+            Delimiter::Whitespace => line.split_whitespace().collect(),
+        }
+    }
+}
+
+/// How to read edges from lines of text.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub delimiter: Delimiter,
+    pub source: usize,          // column of the source node
+    pub target: usize,          // column of the target node
+    pub weight: Option<usize>,  // column of the edge weight, if edges have one
+    pub comments: Vec<String>,  // lines starting with any of these are ignored
+    pub header: usize,          // number of lines to ignore at the start
+    pub strict: bool,           // stop at a malformed line, rather than skip it
+    pub labels: bool,           // nodes are arbitrary labels, rather than numbers
+}
+
+impl Options {
+    /// Tab-separated source and target, `#` comments, no header, strict.
+    pub fn new() -> Options {
+        Options {
+            delimiter: Delimiter::Tab,
+            source: 0,
+            target: 1,
+            weight: None,
+            comments: vec!["#".to_owned()],
+            header: 0,
+            strict: true,
+            labels: false,
+        }
+    }
+
+    /// Registers the command line options understood by `from_matches`.
+    pub fn register(opts: &mut getopts::Options) {
+        opts.optopt("", "delimiter", "column separator (default: tab)", "tab|space|comma|whitespace");
+        opts.optopt("", "source-column", "column of the source node, from 0 (default: 0)", "N");
+        opts.optopt("", "target-column", "column of the target node, from 0 (default: 1)", "N");
+        opts.optopt("", "weight-column", "column of the edge weight, from 0 (default: 2)", "N");
+        opts.optmulti("", "comment", "ignore lines starting with PREFIX (default: #)", "PREFIX");
+        opts.optopt("", "header", "ignore the first N lines (default: 0)", "N");
+        opts.optflag("", "skip-malformed", "skip and count malformed lines, rather than stop");
+        opts.optflag("", "labels", "read nodes as arbitrary labels, numbered in order of appearance");
+    }
+
+    /// Reads the options registered by `register`, with weights if `weighted`.
+    pub fn from_matches(matches: &getopts::Matches, weighted: bool) -> result::Result<Options, String> {
+        let mut options = Options::new();
+        if let Some(delimiter) = matches.opt_str("delimiter") {
+            options.delimiter = Delimiter::from_name(&delimiter).ok_or_else(|| format!("unknown delimiter: {}", delimiter))?;
+        }
+        if let Some(source) = matches.opt_str("source-column") {
+            options.source = source.parse().map_err(|_| format!("malformed source column: {}", source))?;
+        }
+        if let Some(target) = matches.opt_str("target-column") {
+            options.target = target.parse().map_err(|_| format!("malformed target column: {}", target))?;
+        }
+        if weighted {
+            options.weight = Some(2);
+            if let Some(weight) = matches.opt_str("weight-column") {
+                options.weight = Some(weight.parse().map_err(|_| format!("malformed weight column: {}", weight))?);
+            }
+        }
+        if matches.opt_present("comment") {
+            options.comments = matches.opt_strs("comment");
+        }
+        if let Some(header) = matches.opt_str("header") {
+            options.header = header.parse().map_err(|_| format!("malformed header length: {}", header))?;
+        }
+        options.strict = !matches.opt_present("skip-malformed");
+        options.labels = matches.opt_present("labels");
+        Ok(options)
+    }
+
+    // the edge on `line`, or a description of what is wrong with it; labels are
+    // only numbered once the whole line is known to be well formed
+    fn edge(&self, line: &str, dictionary: &mut Dictionary) -> result::Result<(Node, Node, f32), String> {
+        let fields = self.delimiter.split(line);
+        let column = |index: usize, name: &str| {
+            fields.get(index).map(|x| x.trim()).ok_or_else(|| format!("missing {} in column {}", name, index))
+        };
+        let source = column(self.source, "source")?;
+        let target = column(self.target, "target")?;
+        let weight = match self.weight {
+            Some(index) => {
+                let weight = column(index, "weight")?;
+                let value: f32 = weight.parse().map_err(|_| format!("malformed weight: {:?}", weight))?;
+                if !(value >= 0.0 && value.is_finite()) { return Err(format!("weight must be finite and not negative: {:?}", weight)); }
+                value
+            },
+            None => 1.0,
+        };
+        if self.labels {
+            return Ok((dictionary.id(source), dictionary.id(target), weight));
+        }
+        let source = source.parse().map_err(|_| format!("malformed source: {:?}", source))?;
+        let target = target.parse().map_err(|_| format!("malformed target: {:?}", target))?;
+        Ok((source, target, weight))
+    }
+}
+
+/// Iterates over the `(source, target, weight)` edges in the lines of a reader.
+///
+/// Blank lines, comments and header lines are ignored. Weights are 1.0 unless
+/// `Options::weight` names a column. Iteration stops at the first error, which
+/// `finish` reports; in non-strict mode malformed lines are counted instead.
+/// With `Options::labels`, nodes are numbered by `dictionary()`, so that edges
+/// come in no particular order.
+pub struct Parser<R: BufRead> {
+    lines: Lines<R>,
+    name: String,           // names the input in errors
+    options: Options,
+    line: usize,            // number of lines read
+    skipped: usize,         // malformed lines skipped
+    dictionary: Dictionary, // node of each label, with `Options::labels`
+    error: Option<Error>,   // the error reading the input, if any
+    malformed: Option<(usize, String)>, // the line that stopped a strict parse, and its fault
+}
+
+impl<R: BufRead> Parser<R> {
+    pub fn new(reader: R, name: &str, options: Options) -> Parser<R> {
+        Parser { lines: reader.lines(), name: name.to_owned(), options: options, line: 0, skipped: 0, dictionary: Dictionary::new(), error: None, malformed: None }
+    }
+
+    /// The labels of the nodes read so far, with `Options::labels`.
+    pub fn dictionary(&self) -> &Dictionary { &self.dictionary }
+
+    /// Reports the number of malformed lines skipped, or the error that stopped parsing.
+    pub fn finish(self) -> Result<usize> {
+        match (self.error, self.malformed) {
+            (Some(error), _) => Err(error),
+            (None, Some((line, message))) => Err(Error::Format(self.name, format!("line {}: {}", line, message))),
+            (None, None) => Ok(self.skipped),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Parser<R> {
+    type Item = (Node, Node, f32);
+    fn next(&mut self) -> Option<(Node, Node, f32)> {
+        while self.error.is_none() && self.malformed.is_none() {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(error) => { self.error = Some(Error::Io(self.name.clone(), error)); break; },
+            };
+            self.line += 1;
+            if self.line <= self.options.header || line.trim().is_empty() { continue; }
+            if self.options.comments.iter().any(|x| line.starts_with(&x[..])) { continue; }
+            match self.options.edge(&line, &mut self.dictionary) {
+                Ok(edge) => return Some(edge),
+                Err(message) => {
+                    if self.options.strict {
+                        self.malformed = Some((self.line, message));
+                    }
+                    else {
+                        self.skipped += 1;
+                    }
+                },
+            }
+        }
+        None
+    }
+}
+
+/// Parses the edge list file `filename` on `threads` threads, as `Parser` would.
+///
+/// The file is split into byte ranges ending at line breaks, and each thread
+/// parses one range into a `<scratch>.chunk-<i>` file. Reading the returned
+/// `Chunks` yields the edges in the order of the file, so that encoding them
+/// gives the same graph as parsing sequentially. Errors are reported with the
+/// line number in the whole file, before any edge is returned.
+///
+/// The file must not be compressed, and nodes must not be labels, as labels
+/// are numbered in order of appearance.
+pub fn parse_parallel(filename: &str, scratch: &str, options: &Options, threads: usize) -> Result<Chunks> {
+    assert!(!options.labels, "labels cannot be parsed in parallel");
+
+    // skip the header here, as it may span several ranges
+    let size = fs::metadata(filename).in_file(filename)?.len();
+    let mut reader = BufReader::new(File::open(filename).in_file(filename)?);
+    let mut rest = Vec::new();
+    let mut first = 0;
+    for _ in 0..options.header {
+        first += reader.read_until(b'\n', &mut rest).in_file(filename)? as u64;
+    }
+
+    // start each range after the first line break at or past its share of the file
+    let mut bounds = vec![first];
+    for thread in 1..threads {
+        let share = ::std::cmp::max(first + (size - first) * thread as u64 / threads as u64, bounds[thread - 1]);
+        reader.seek(SeekFrom::Start(share)).in_file(filename)?;
+        let skip = if share == first { 0 } else { reader.read_until(b'\n', &mut rest).in_file(filename)? as u64 };
+        bounds.push(::std::cmp::min(share + skip, size));
+    }
+    bounds.push(size);
+
+    let mut chunks = Chunks { names: Vec::new(), reader: None, weighted: options.weight.is_some(), skipped: 0, error: None };
+    let mut handles = Vec::new();
+    for thread in 0..threads {
+        let filename = filename.to_owned();
+        let name = format!("{}.chunk-{}", scratch, thread);
+        let (start, limit) = (bounds[thread], bounds[thread + 1]);
+        let mut options = options.clone();
+        options.header = 0;
+        chunks.names.push(name.clone());
+        handles.push(thread::spawn(move || parse_chunk(&filename, &name, start, limit, options)));
+    }
+
+    // wait for all threads before reporting errors, so that no chunk is left behind
+    let results: Vec<_> = handles.into_iter().map(|x| x.join().expect("parser thread panicked")).collect();
+
+    // renumber the first failure by the lines of the header and the ranges before it
+    let mut lines = options.header;
+    let mut failure = None;
+    for result in results {
+        let (read, skipped, malformed) = result?;
+        if failure.is_none() {
+            failure = malformed.map(|(line, message)| (lines + line, message));
+        }
+        lines += read;
+        chunks.skipped += skipped;
+    }
+    if let Some((line, message)) = failure {
+        return Err(Error::Format(filename.to_owned(), format!("line {}: {}", line, message)));
+    }
+    Ok(chunks)
+}
+
+// parses the bytes of `filename` from `start` to `limit` into the chunk file
+// `name`, reporting the lines read, lines skipped and any malformed line
+fn parse_chunk(filename: &str, name: &str, start: u64, limit: u64, options: Options) -> Result<(usize, usize, Option<(usize, String)>)> {
+    let weighted = options.weight.is_some();
+    let mut file = File::open(filename).in_file(filename)?;
+    file.seek(SeekFrom::Start(start)).in_file(filename)?;
+    let mut parser = Parser::new(BufReader::new(file).take(limit - start), filename, options);
+    let mut writer = BufWriter::new(File::create(name).in_file(name)?);
+    for (source, target, weight) in parser.by_ref() {
+        write_edge(&mut writer, source, target, weight, weighted).in_file(name)?;
+    }
+    writer.flush().in_file(name)?;
+    match parser.error {
+        Some(error) => Err(error),
+        None => Ok((parser.line, parser.skipped, parser.malformed)),
+    }
+}
+
+/// The edges parsed by `parse_parallel`, read back in order.
+///
+/// The chunk files are removed when `Chunks` is dropped.
+pub struct Chunks {
+    names: Vec<String>,                     // chunk files not yet finished, in order
+    reader: Option<BufReader<File>>,        // the chunk being read, `names[0]`
+    weighted: bool,
+    skipped: usize,                         // malformed lines skipped, over all chunks
+    error: Option<Error>,                   // the first error reading a chunk
+}
+
+impl Chunks {
+    /// Reports the number of malformed lines skipped, or the error that stopped reading.
+    pub fn finish(mut self) -> Result<usize> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(self.skipped),
+        }
+    }
+}
+
+impl Iterator for Chunks {
+    type Item = (Node, Node, f32);
+    fn next(&mut self) -> Option<(Node, Node, f32)> {
+        while self.error.is_none() && self.names.len() > 0 {
+            if self.reader.is_none() {
+                match File::open(&self.names[0]) {
+                    Ok(file) => self.reader = Some(BufReader::new(file)),
+                    Err(error) => { self.error = Some(Error::Io(self.names[0].clone(), error)); break; },
+                }
+            }
+            match read_edge(self.reader.as_mut().unwrap(), self.weighted) {
+                Ok(Some(edge)) => return Some(edge),
+                Ok(None) => {
+                    self.reader = None;
+                    let _ = fs::remove_file(self.names.remove(0));
+                },
+                Err(error) => { self.error = Some(Error::Io(self.names[0].clone(), error)); },
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Chunks {
+    fn drop(&mut self) {
+        for name in &self.names { let _ = fs::remove_file(name); }
+    }
+}
+
+#[test]
+fn parse_lines() {
+    let input = "src,dst,w\n% comment\n0, 1,0.5\n\n2,x,1.0\n3,4\n5,6,2.0\n";
+    let mut options = Options::new();
+    options.delimiter = Delimiter::Comma;
+    options.weight = Some(2);
+    options.comments = vec!["%".to_owned()];
+    options.header = 1;
+
+    let mut parser = Parser::new(input.as_bytes(), "input", options.clone());
+    assert_eq!(parser.by_ref().collect::<Vec<_>>(), vec![(0, 1, 0.5)]);
+    let error = parser.finish().unwrap_err();
+    assert_eq!(format!("{}", error), "input: line 5: malformed target: \"x\"");
+
+    options.strict = false;
+    let mut parser = Parser::new(input.as_bytes(), "input", options);
+    assert_eq!(parser.by_ref().collect::<Vec<_>>(), vec![(0, 1, 0.5), (5, 6, 2.0)]);
+    assert_eq!(parser.finish().unwrap(), 2);
+
+    let mut options = Options::new();
+    options.delimiter = Delimiter::Whitespace;
+    options.labels = true;
+    let mut parser = Parser::new("b a\na  c\n".as_bytes(), "input", options);
+    assert_eq!(parser.by_ref().collect::<Vec<_>>(), vec![(0, 1, 1.0), (1, 2, 1.0)]);
+    assert_eq!(parser.dictionary().labels(), &["b", "a", "c"]);
+
+    let mut options = Options::new();
+    options.delimiter = Delimiter::Whitespace;
+    options.weight = Some(2);
+    let mut parser = Parser::new("0 1 0.5\n1 2 -1\n".as_bytes(), "input", options);
+    assert_eq!(parser.by_ref().collect::<Vec<_>>(), vec![(0, 1, 0.5)]);
+    assert_eq!(format!("{}", parser.finish().unwrap_err()), "input: line 2: weight must be finite and not negative: \"-1\"");
+}
+
+#[test]
+fn parse_lines_parallel() {
+    let directory = ::tempdir::TempDir::new("parse_lines_parallel").unwrap();
+    let filename = directory.path().join("edges.txt").to_string_lossy().into_owned();
+    let scratch = directory.path().join("graph").to_string_lossy().into_owned();
+
+    let mut text = String::from("# edges\n");
+    for node in 0..100 { text.push_str(&format!("{}\t{}\n", node, (node * 7) % 100)); }
+    File::create(&filename).unwrap().write_all(text.as_bytes()).unwrap();
+
+    let sequential = Parser::new(text.as_bytes(), "edges", Options::new()).collect::<Vec<_>>();
+    for &threads in &[1, 3, 8, 500] {
+        let chunks = parse_parallel(&filename, &scratch, &Options::new(), threads).unwrap();
+        assert_eq!(chunks.collect::<Vec<_>>(), sequential);
+    }
+    assert!(!::std::path::Path::new(&format!("{}.chunk-0", scratch)).exists());
+
+    // the malformed line is numbered within the whole file
+    text.push_str("100\tx\n");
+    File::create(&filename).unwrap().write_all(text.as_bytes()).unwrap();
+    match parse_parallel(&filename, &scratch, &Options::new(), 4) {
+        Err(error) => assert_eq!(format!("{}", error), format!("{}: line 102: malformed target: \"x\"", filename)),
+        Ok(_) => panic!("malformed line accepted"),
+    }
+
+    // header lines are skipped wherever the ranges fall
+    let mut options = Options::new();
+    options.header = 3;
+    let text = format!("source\ttarget\nfrom\tto\n{}", text);
+    File::create(&filename).unwrap().write_all(text.as_bytes()).unwrap();
+    for &threads in &[1, 3, 500] {
+        match parse_parallel(&filename, &scratch, &options, threads) {
+            Err(error) => assert_eq!(format!("{}", error), format!("{}: line 104: malformed target: \"x\"", filename)),
+            Ok(_) => panic!("malformed line accepted"),
+        }
+    }
+    options.strict = false;
+    for &threads in &[1, 3, 500] {
+        let chunks = parse_parallel(&filename, &scratch, &options, threads).unwrap();
+        assert_eq!(chunks.collect::<Vec<_>>(), sequential);
+    }
+}
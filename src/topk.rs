@@ -0,0 +1,56 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use timely::dataflow::scopes::root::Root;
+use timely_communication::Allocate;
+
+use gather;
+use graphmap::Node;
+
+// a (node, rank) pair ordered by rank, with ties going to the smaller node
+#[derive(Copy, Clone, PartialEq)]
+struct Ranked(Node, f32);
+
+impl Eq for Ranked { }
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Ranked) -> Ordering {
+        self.1.partial_cmp(&other.1).unwrap_or(Ordering::Equal)
+              .then(other.0.cmp(&self.0))
+    }
+}
+
+/// Returns the `k` highest ranked of `ranks`, in decreasing order of rank.
+pub fn local_top_k<I: IntoIterator<Item=(Node, f32)>>(ranks: I, k: usize) -> Vec<(Node, f32)> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for (node, rank) in ranks {
+        heap.push(Reverse(Ranked(node, rank)));
+        if heap.len() > k { heap.pop(); }
+    }
+    let mut top: Vec<_> = heap.into_iter().map(|Reverse(x)| x).collect();
+    top.sort_by(|x, y| y.cmp(x));
+    top.into_iter().map(|Ranked(node, rank)| (node, rank)).collect()
+}
+
+/// Collects the `k` highest ranked nodes across the shards returned by `run`.
+///
+/// Every worker must call `top_k`; each contributes only its own top `k`, so at
+/// most `k * peers` pairs travel to worker 0, which receives the global top `k`
+/// in decreasing order of rank. The other workers receive an empty vector.
+pub fn top_k<A: Allocate>(root: &mut Root<A>, shard: &[(Node, f32)], k: usize) -> Vec<(Node, f32)> {
+    let candidates = local_top_k(shard.iter().cloned(), k);
+    let candidates = gather(root, candidates);
+    local_top_k(candidates, k)
+}
+
+#[test]
+fn test_local_top_k() {
+    let ranks = vec![(0, 0.5), (1, 2.0), (2, 1.0), (3, 2.0), (4, 0.1)];
+    assert_eq!(local_top_k(ranks.clone(), 3), vec![(1, 2.0), (3, 2.0), (2, 1.0)]);
+    assert_eq!(local_top_k(ranks.clone(), 0), vec![]);
+    assert_eq!(local_top_k(ranks.clone(), 9).len(), 5);
+}
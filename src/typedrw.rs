@@ -5,6 +5,8 @@ use std::fs::File;
 use std::marker::PhantomData;
 use memmap::{ Mmap, Protection, MmapViewSync };
 
+use error::{ Error, Result, InFile };
+
 pub struct TypedMemoryMap<T:Copy> {
     map:    MmapViewSync,       // mapped file
     len:    usize,              // in bytes (needed because map extends to full block)
@@ -12,14 +14,20 @@ pub struct TypedMemoryMap<T:Copy> {
 }
 
 impl<T:Copy> TypedMemoryMap<T> {
-    pub fn new(filename: String) -> TypedMemoryMap<T> {
-        let file = File::open(filename).unwrap();
-        let size = file.metadata().unwrap().len() as usize;
-        TypedMemoryMap {
-            map: Mmap::open(&file, Protection::Read).unwrap().into_view_sync(),
+    pub fn new(filename: String) -> Result<TypedMemoryMap<T>> {
+        let file = File::open(&filename).in_file(&filename)?;
+        let size = file.metadata().in_file(&filename)?.len() as usize;
+        if size % mem::size_of::<T>() != 0 {
+            return Err(Error::Format(filename, format!("size {} is not a multiple of {}", size, mem::size_of::<T>())));
+        }
+        // empty files cannot be mapped; map a placeholder instead
+        let map = if size > 0 { Mmap::open(&file, Protection::Read) }
+                  else        { Mmap::anonymous(1, Protection::Read) };
+        Ok(TypedMemoryMap {
+            map: map.in_file(&filename)?.into_view_sync(),
             len: size / mem::size_of::<T>(),
             phn: PhantomData,
-        }
+        })
     }
 }
 
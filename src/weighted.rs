@@ -0,0 +1,198 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::mem;
+
+use timely::progress::timestamp::RootTimestamp;
+use timely::dataflow::operators::*;
+use timely::dataflow::scopes::root::Root;
+use timely::dataflow::channels::pact::Exchange;
+use timely::drain::DrainExt;
+use timely_communication::Allocate;
+
+use graphmap::{WeightedGraph, Node};
+use sorting::{SegmentList, radix_sort_node};
+use partition::Partition;
+use ranking::Ranking;
+use {Config, aggregate};
+
+/// Runs PageRank over `graph`, splitting each node's rank over its out-edges in
+/// proportion to their weights rather than evenly.
+///
+/// Behaves as `run` otherwise, with nodes whose out-edges weigh nothing in total
+/// treated as dangling, except that `config.cache` and `config.hubs` must not be
+/// set: weighted edges are neither cached nor split.
+pub fn run_weighted<G:WeightedGraph,A:Allocate>(root:&mut Root<A>, graph:&G, config:&Config) -> Vec<(Node, f32)> {
+
+    let index = root.index() as usize;
+    let peers = root.peers() as usize;
+    assert!(config.cache.is_none(), "weighted runs do not cache transposed edges");
+    assert!(config.hubs.is_empty(), "weighted runs do not split hubs");
+
+    let workers = config.workers;
+    let iterations = config.iterations;
+    let damping = config.damping;
+
+    let nodes = graph.nodes();
+    let partition = Partition::new(config.partition, graph, peers);
+    let local = partition.local(index);
+    let mut ranking = Ranking::new(config, &partition, index, &[config.teleport.clone()]);
+    let control = ranking.control();
+
+    let mut segments = SegmentList::new(1024);
+
+    // as in `run`, except that deg holds total out-weights, and wgt holds the
+    // weight of each edge in trn.
+    let mut deg = vec![];
+    let mut rev = vec![];
+    let mut trn = vec![];
+    let mut wgt = vec![];
+
+    let result = Rc::new(RefCell::new(Vec::new()));
+    let shard = result.clone();
+
+    let mut input = root.scoped(|builder| {
+
+        let (input, edges) = builder.new_input::<(Node, Node, f32)>();
+        let (cycle, ranks) = builder.loop_variable::<(Node, f32)>(iterations, 1);
+
+        let owner = partition.clone();
+        let route = partition.clone();
+        let layout = partition.clone();
+
+        let mut ranks = edges.binary_notify(&ranks,
+                            Exchange::new(move |x: &(Node,Node,f32)| owner.owner(x.0 as usize) as u64),
+                            Exchange::new(move |x: &(Node,f32)| {
+                                if (x.0 as usize) < control { route.owner(x.0 as usize) as u64 } else { x.0 as u64 }
+                            }),
+                            "pagerank-weighted",
+                            vec![RootTimestamp::new(0)],
+                            move |input1, input2, output, notificator| {
+
+            while let Some((_iter, data)) = input1.next() {
+                segments.push(data.drain_temp());
+            }
+
+            while let Some((iter, _)) = notificator.next() {
+
+                if index == 0 { println!("starting iteration {}", iter.inner); }
+
+                if iter.inner == 0 {
+                    let segs = segments.finalize();
+                    let (a, b, c, d) = transpose_weighted(segs, &layout);
+                    deg = a; rev = b; trn = c; wgt = d;
+                }
+
+                if ranking.start(iter.inner) {
+                    shard.borrow_mut().extend(ranking.shard(&layout));
+                    continue;
+                }
+
+                let mut next = iter.clone();
+                next.inner += 1;
+                notificator.notify_at(&next);
+
+                let mut session = output.session(&iter);
+                for (id, values) in ranking.aggregates(iter.inner, |s| deg[s] <= 0.0) {
+                    session.give((id, values[0]));
+                }
+
+                // from here on, src holds the amount to propagate per unit of weight
+                for s in 0..deg.len() {
+                    ranking.src[s] = if deg[s] > 0.0 { damping * ranking.src[s] / deg[s] } else { 0.0 };
+                }
+
+                let src = &ranking.src;
+                let mut trn_slice = &trn[..];
+                let mut wgt_slice = &wgt[..];
+                for &(dst, deg) in &rev {
+                    let mut accum = 0.0;
+                    for (&s, &w) in trn_slice[..deg as usize].iter().zip(&wgt_slice[..deg as usize]) {
+                        unsafe { accum += *src.get_unchecked(s as usize) * w; }
+                    }
+                    trn_slice = &trn_slice[deg as usize..];
+                    wgt_slice = &wgt_slice[deg as usize..];
+                    session.give((dst, accum));
+                }
+
+                ranking.reset();
+            }
+
+            while let Some((iter, data)) = input2.next() {
+                notificator.notify_at(&iter);
+                for &(node, rank) in data.iter() {
+                    let node = node as usize;
+                    if node < control { ranking.add(layout.slot(node), &[rank]); }
+                    else              { ranking.receive(node, &[rank]); }
+                }
+            }
+        });
+
+        if config.use_process_local {
+            ranks = aggregate(&ranks, nodes, workers, index, control, 1);
+        }
+
+        ranks.connect_loop(cycle);
+
+        input
+    });
+
+    for slot in 0..local {
+        let node = partition.node(index, slot);
+        for (dst, weight) in graph.edges(node).iter().zip(graph.weights(node)) {
+            input.send((node as Node, *dst, *weight));
+        }
+    }
+    input.close();
+    while root.step() { };
+
+    let shard = mem::replace(&mut *result.borrow_mut(), Vec::new());
+    shard
+}
+
+// returns out-weights by slot, (dst, deg) pairs, and lists of endpoint slots and their weights
+fn transpose_weighted(mut edges: Vec<Vec<(Node, Node, f32)>>, partition: &Partition) -> (Vec<f32>, Vec<(Node, u32)>, Vec<Node>, Vec<f32>) {
+
+    let mut deg = vec![0.0; partition.slots()];
+    for list in &edges {
+        for &(s, _, w) in list {
+            deg[partition.slot(s as usize)] += w;
+        }
+    }
+
+    radix_sort_node(&mut edges, &mut Vec::new(), &|&(_,d,_)| d);
+
+    let mut rev = Vec::<(Node,u32)>::with_capacity(deg.len());
+    let mut trn = Vec::with_capacity(edges.len() * 1024);
+    let mut wgt = Vec::with_capacity(edges.len() * 1024);
+    for list in edges {
+        for (s,d,w) in list {
+            if (rev.len() == 0) || (rev[rev.len()-1].0 < d) {
+                rev.push((d, 0u32));
+            }
+
+            let len = rev.len();
+            rev[len-1].1 += 1;
+            trn.push(partition.slot(s as usize) as Node);
+            wgt.push(w);
+        }
+    }
+
+    (deg, rev, trn, wgt)
+}
+
+#[test]
+fn test_transpose_weighted() {
+    // weighted three branch star, one worker
+    let (deg,rev,trn,wgt) = transpose_weighted(vec!(vec!((0,3,1.0),(0,1,2.0),(0,2,0.5))), &Partition::modulo(4, 1));
+    assert_eq!(*deg, [3.5, 0.0, 0.0, 0.0]);
+    assert_eq!(*rev, [(1,1),(2,1),(3,1)]);
+    assert_eq!(*trn, [0,0,0]);
+    assert_eq!(*wgt, [2.0,0.5,1.0]);
+
+    // weighted pipe, second worker of two
+    let (deg,rev,trn,wgt) = transpose_weighted(vec!(vec!((1,2,4.0),(3,0,0.0))), &Partition::modulo(4, 2));
+    assert_eq!(*deg, [4.0, 0.0]);
+    assert_eq!(*rev, [(0,1),(2,1)]);
+    assert_eq!(*trn, [1,0]);
+    assert_eq!(*wgt, [0.0,4.0]);
+}